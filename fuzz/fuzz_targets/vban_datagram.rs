@@ -0,0 +1,35 @@
+#![no_main]
+
+// Anything arriving on the VBAN port, decoded the way the VBAN processor thread does and handed
+// to a bridge along with a refresh, as the bridge thread would
+
+use libfuzzer_sys::fuzz_target;
+use vban_xctrl::*;
+
+fuzz_target!(|data: &[u8]| {
+    let mut config = Config::default();
+    config.xctrl.surfaces = vec!["10.0.0.2".to_string()];
+    config.vban.host = "10.0.0.3".to_string();
+    let mut bridge = Bridge::new(&config).unwrap();
+    let rt_stream = stream_name(&bridge.streams.rt);
+
+    // Random bytes are rarely a whole RT packet, so the input also fills in the body of one that
+    // gets past the header checks, to reach the bridge itself
+    let mut packet = vec![0u8; 1412];
+    packet[0..4].copy_from_slice(b"VBAN");
+    packet[4] = VBANProtocol::Service as u8;
+    packet[8..24].copy_from_slice(&rt_stream);
+    let body = std::cmp::min(data.len(), packet.len() - 28);
+    packet[28..(28 + body)].copy_from_slice(&data[..body]);
+    // Pings from anywhere on the LAN are read before the bridge knows who sent them
+    if let Ok(ping) = PingPacket::parse(data) {
+        let _ = VbanHost::from_ping("10.0.0.3:6980".parse().unwrap(), &ping).to_string();
+    }
+
+    for datagram in [data, &packet[..]].iter() {
+        if let Ok(Some(event)) = decode_vban(datagram, &rt_stream) {
+            bridge.handle(event);
+            bridge.refresh();
+        }
+    }
+});
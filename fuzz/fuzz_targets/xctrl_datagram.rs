@@ -0,0 +1,22 @@
+#![no_main]
+
+// Anything arriving on the X-Touch port, decoded the way the X-Touch processor thread does and
+// handed to a bridge along with a refresh, as the bridge thread would
+
+use libfuzzer_sys::fuzz_target;
+use vban_xctrl::*;
+
+fuzz_target!(|data: &[u8]| {
+    let mut config = Config::default();
+    config.xctrl.surfaces = vec!["10.0.0.2".to_string(), "10.0.0.4".to_string()];
+    config.vban.host = "10.0.0.3".to_string();
+    let mut bridge = Bridge::new(&config).unwrap();
+
+    let _ = XctrlMessage::parse(data);
+    if let Ok(events) = decode_xctrl(1, data) {
+        for event in events {
+            bridge.handle(event);
+        }
+        bridge.refresh();
+    }
+});
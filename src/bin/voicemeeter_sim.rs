@@ -0,0 +1,36 @@
+use std::net::UdpSocket;
+use std::env;
+use std::process;
+
+extern crate vban_xctrl;
+
+use vban_xctrl::*;
+
+// Pretends to be VoiceMeeter, for trying the bridge out without it
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let scenario = match args.len() {
+        2 => Ok(SimScenario::default()),
+        3 => SimScenario::load(&args[2]),
+        _ => {
+            eprintln!("Usage: voicemeeter_sim <bind ip:port> [scenario file]");
+            process::exit(1);
+        }
+    };
+    let scenario = match scenario {
+        Ok(scenario) => scenario,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let sim = VoiceMeeterSim::from_scenario(&scenario).expect("the scenario was already checked");
+    let socket = UdpSocket::bind(&args[1]).unwrap();
+    println!("Simulating VoiceMeeter {} on {}", scenario.edition, args[1]);
+
+    let (_, threads) = start_simulator(sim, socket, scenario.interval(), scenario.steps);
+    for handle in threads {
+        handle.join().unwrap();
+    }
+}
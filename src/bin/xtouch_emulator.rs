@@ -0,0 +1,278 @@
+use std::env;
+use std::io::{self, Write};
+use std::net::UdpSocket;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+extern crate crossterm;
+extern crate vban_xctrl;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use vban_xctrl::*;
+
+// Stands in for an X-Touch, showing what the bridge sends it in the terminal
+
+const STRIP_WIDTH: u16 = 11;
+// A small step and a big step of fader travel
+const FADER_STEP: i32 = 32767 / 64;
+const FADER_COARSE_STEP: i32 = 32767 / 8;
+
+// The buttons outside the channel strips the bridge uses, in the order they're drawn
+const GLOBAL_BUTTONS: [(&str, u8); 14] = [
+    ("Track", XctrlButtonType::Track as u8),
+    ("Pan", XctrlButtonType::Pan as u8),
+    ("EQ", XctrlButtonType::Eq as u8),
+    ("Send", XctrlButtonType::Send as u8),
+    ("Flip", XctrlButtonType::Flip as u8),
+    ("Bank<", XctrlButtonType::FaderBank as u8),
+    ("Bank>", XctrlButtonType::FaderBank as u8 + 1),
+    ("Chan<", XctrlButtonType::ChannelBank as u8),
+    ("Chan>", XctrlButtonType::ChannelBank as u8 + 1),
+    ("Inputs", XctrlButtonType::Inputs as u8),
+    ("Aux", XctrlButtonType::Aux as u8),
+    ("Buses", XctrlButtonType::Buses as u8),
+    ("Outputs", XctrlButtonType::Outputs as u8),
+    ("Shift", XctrlButtonType::Shift as u8)
+];
+
+fn display_color(color: u8) -> Option<Color> {
+    match color & 0x07 {
+        0x01 => Some(Color::Red),
+        0x02 => Some(Color::Green),
+        0x03 => Some(Color::Yellow),
+        0x04 => Some(Color::Blue),
+        0x05 => Some(Color::Magenta),
+        0x06 => Some(Color::Cyan),
+        0x07 => Some(Color::White),
+        _ => None,
+    }
+}
+
+fn text(line: &[u8; 7]) -> String {
+    line.iter().map(|c| if *c >= 0x20 && *c < 0x7f { *c as char } else { ' ' }).collect()
+}
+
+// The 11 LEDs around an encoder, for the ring's mode and value
+fn ring_leds(ring: &XctrlEncoderRing) -> String {
+    if ring.value == 0 {
+        return ".".repeat(11);
+    }
+    let position = ring.value as i32 - 1;
+    let lit = |led: i32| -> bool {
+        match ring.mode {
+            0x10 => led >= position.min(5) && led <= position.max(5),
+            0x20 => led <= position,
+            0x30 => (led - 5).abs() <= position,
+            _ => led == position,
+        }
+    };
+    (0..11).map(|led| if lit(led) { '*' } else { '.' }).collect()
+}
+
+fn bar(filled: usize, width: usize) -> String {
+    let filled = filled.min(width);
+    format!("{}{}", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+fn lit_button(out: &mut io::Stdout, label: &str, lit: bool, color: Color) -> io::Result<()> {
+    if lit {
+        queue!(out, SetBackgroundColor(color), SetForegroundColor(Color::Black), Print(label), ResetColor)?;
+    } else {
+        queue!(out, Print(label))?;
+    }
+    Ok(())
+}
+
+fn draw(out: &mut io::Stdout, emulator: &XtouchEmulator, bridge: &str, selected: usize) -> io::Result<()> {
+    let surface = &emulator.surface;
+    queue!(out, MoveTo(0, 0), Clear(ClearType::All))?;
+    let status = if emulator.connected { "connected" } else { "waiting for the bridge" };
+    queue!(out, Print(format!("X-Touch emulator, bridge at {}, {}", bridge, status)))?;
+
+    for strip in 0..9 {
+        let x = strip as u16 * STRIP_WIDTH;
+        let heading = if strip == 8 { "Main".to_string() } else { format!("{}", strip + 1) };
+        if strip == selected {
+            queue!(out, MoveTo(x, 2), SetAttribute(Attribute::Reverse), Print(format!(" {:<9}", heading)), SetAttribute(Attribute::Reset))?;
+        } else {
+            queue!(out, MoveTo(x, 2), Print(format!(" {:<9}", heading)))?;
+        }
+
+        if strip < 8 {
+            let display = &surface.displays[strip];
+            for (row, line) in [&display.top_text, &display.bottom_text].iter().enumerate() {
+                queue!(out, MoveTo(x + 1, 3 + row as u16))?;
+                match display_color(display.color) {
+                    // The inverted colours light the background instead of the text
+                    Some(color) if display.color & 0x40 == 0x40 => queue!(out, SetBackgroundColor(color), SetForegroundColor(Color::Black))?,
+                    Some(color) => queue!(out, SetForegroundColor(color))?,
+                    None => queue!(out, SetForegroundColor(Color::DarkGrey))?
+                };
+                queue!(out, Print(format!(" {} ", text(line))), ResetColor)?;
+            }
+
+            let lit = |row: XctrlButtonType| emulator.button_lit(row as u8 + strip as u8);
+            queue!(out, MoveTo(x + 1, 6), Print(ring_leds(&surface.rings[strip])))?;
+            queue!(out, MoveTo(x + 1, 7))?;
+            lit_button(out, "O", lit(XctrlButtonType::Encoder), Color::White)?;
+            queue!(out, MoveTo(x + 1, 8), Print(bar(surface.meters[strip].level as usize / 2, 8)))?;
+            queue!(out, MoveTo(x + 1, 10))?;
+            lit_button(out, "REC", lit(XctrlButtonType::Rec), Color::Red)?;
+            queue!(out, Print(" "))?;
+            lit_button(out, "SOLO", lit(XctrlButtonType::Solo), Color::Yellow)?;
+            queue!(out, MoveTo(x + 1, 11))?;
+            lit_button(out, "MUTE", lit(XctrlButtonType::Mute), Color::Red)?;
+            queue!(out, Print(" "))?;
+            lit_button(out, "SEL", lit(XctrlButtonType::Select), Color::Green)?;
+        }
+
+        let level = surface.faders[strip].level as f32 / 32767.0;
+        queue!(out, MoveTo(x + 1, 13), Print(bar((level * 9.0).round() as usize, 9)))?;
+        queue!(out, MoveTo(x + 1, 14), Print(format!("{:>5.1}%", level * 100.0)))?;
+    }
+
+    queue!(out, MoveTo(0, 16))?;
+    for (label, id) in GLOBAL_BUTTONS.iter() {
+        lit_button(out, label, emulator.button_lit(*id), Color::Yellow)?;
+        queue!(out, Print(" "))?;
+    }
+
+    let help = [
+        "1-8 pick a strip, 9 the main fader   up/down move the fader, page up/down in bigger steps",
+        "left/right turn the encoder, space pushes it   r s m enter: rec, solo, mute, select",
+        "[ ] fader bank   , . channel bank   f flip   t p e n: track, pan, eq, send",
+        "i x b o: inputs, aux, buses, outputs   q quits"
+    ];
+    for (row, line) in help.iter().enumerate() {
+        queue!(out, MoveTo(0, 18 + row as u16), SetForegroundColor(Color::DarkGrey), Print(line), ResetColor)?;
+    }
+    out.flush()
+}
+
+// The datagram a key sends to the bridge, if it sends one. Moving between strips only changes
+// which one the other keys act on.
+fn key_message(emulator: &mut XtouchEmulator, key: KeyCode, selected: &mut usize) -> Option<Vec<u8>> {
+    let strip = (*selected).min(7);
+    let message = match key {
+        KeyCode::Char(c @ '1'..='9') => {
+            *selected = c as usize - '1' as usize;
+            return None;
+        },
+        KeyCode::Up => emulator.nudge_fader(*selected, FADER_STEP),
+        KeyCode::Down => emulator.nudge_fader(*selected, -FADER_STEP),
+        KeyCode::PageUp => emulator.nudge_fader(*selected, FADER_COARSE_STEP),
+        KeyCode::PageDown => emulator.nudge_fader(*selected, -FADER_COARSE_STEP),
+        KeyCode::Left if *selected < 8 => emulator.turn_encoder(strip, -1),
+        KeyCode::Right if *selected < 8 => emulator.turn_encoder(strip, 1),
+        KeyCode::Char(' ') if *selected < 8 => emulator.press_strip(XctrlButtonType::Encoder, strip),
+        KeyCode::Char('r') if *selected < 8 => emulator.press_strip(XctrlButtonType::Rec, strip),
+        KeyCode::Char('s') if *selected < 8 => emulator.press_strip(XctrlButtonType::Solo, strip),
+        KeyCode::Char('m') if *selected < 8 => emulator.press_strip(XctrlButtonType::Mute, strip),
+        KeyCode::Enter if *selected < 8 => emulator.press_strip(XctrlButtonType::Select, strip),
+        KeyCode::Char('[') => emulator.press(XctrlButtonType::FaderBank as u8),
+        KeyCode::Char(']') => emulator.press(XctrlButtonType::FaderBank as u8 + 1),
+        KeyCode::Char(',') => emulator.press(XctrlButtonType::ChannelBank as u8),
+        KeyCode::Char('.') => emulator.press(XctrlButtonType::ChannelBank as u8 + 1),
+        KeyCode::Char('f') => emulator.press(XctrlButtonType::Flip as u8),
+        KeyCode::Char('t') => emulator.press(XctrlButtonType::Track as u8),
+        KeyCode::Char('p') => emulator.press(XctrlButtonType::Pan as u8),
+        KeyCode::Char('e') => emulator.press(XctrlButtonType::Eq as u8),
+        KeyCode::Char('n') => emulator.press(XctrlButtonType::Send as u8),
+        KeyCode::Char('i') => emulator.press(XctrlButtonType::Inputs as u8),
+        KeyCode::Char('x') => emulator.press(XctrlButtonType::Aux as u8),
+        KeyCode::Char('b') => emulator.press(XctrlButtonType::Buses as u8),
+        KeyCode::Char('o') => emulator.press(XctrlButtonType::Outputs as u8),
+        _ => return None
+    };
+    Some(message)
+}
+
+fn run(socket: Arc<UdpSocket>, emulator: Arc<Mutex<XtouchEmulator>>, bridge: &str) -> io::Result<()> {
+    let mut out = io::stdout();
+    let mut selected = 0;
+    // Only redrawn when something has changed, so the screen doesn't flicker
+    let mut drawn: Option<(XctrlState, bool, usize)> = None;
+    loop {
+        {
+            let emulator = emulator.lock().unwrap();
+            let now = (emulator.surface.clone(), emulator.connected, selected);
+            if drawn.as_ref() != Some(&now) {
+                draw(&mut out, &emulator, bridge, selected)?;
+                drawn = Some(now);
+            }
+        }
+        if !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(KeyEvent { kind: KeyEventKind::Release, .. }) => continue,
+            Event::Key(KeyEvent { code, .. }) => code,
+            _ => continue
+        };
+        if key == KeyCode::Char('q') || key == KeyCode::Esc {
+            return Ok(());
+        }
+        let message = key_message(&mut emulator.lock().unwrap(), key, &mut selected);
+        if let Some(message) = message {
+            socket.send_to(&message, bridge)?;
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: xtouch_emulator <bind ip:port> <bridge ip:port>");
+        process::exit(1);
+    }
+    let bridge = args[2].clone();
+    let socket = match UdpSocket::bind(&args[1]) {
+        Ok(socket) => Arc::new(socket),
+        Err(e) => {
+            eprintln!("Couldn't bind to {}: {}", args[1], e);
+            process::exit(1);
+        }
+    };
+    let emulator = Arc::new(Mutex::new(XtouchEmulator::new()));
+
+    {
+        let (socket, emulator) = (socket.clone(), emulator.clone());
+        thread::spawn(move || {
+            loop {
+                let mut buf = [0; 2048];
+                if let Ok((amt, _)) = socket.recv_from(&mut buf) {
+                    emulator.lock().unwrap().apply(&buf[..amt]);
+                }
+            }
+        });
+    }
+    {
+        let (socket, emulator, bridge) = (socket.clone(), emulator.clone(), bridge.clone());
+        thread::spawn(move || {
+            loop {
+                let probe = emulator.lock().unwrap().probe();
+                if let Err(e) = socket.send_to(&probe, &bridge) {
+                    eprintln!("Error sending the probe: {:?}", e);
+                }
+                thread::sleep(Duration::from_secs(2));
+            }
+        });
+    }
+
+    let mut out = io::stdout();
+    terminal::enable_raw_mode().expect("couldn't put the terminal into raw mode");
+    execute!(out, EnterAlternateScreen, Hide).unwrap();
+    let result = run(socket, emulator, &bridge);
+    execute!(out, Show, LeaveAlternateScreen).unwrap();
+    terminal::disable_raw_mode().unwrap();
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,731 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use packed_struct::prelude::*;
+
+use config::{ButtonFunction, ButtonRows, Config, ConfigError, Page, PageButton, VbanStreams};
+use encoder::{EncoderAssignment, EncoderState};
+use fader_law::FaderRange;
+use label::format_label;
+use rt_state::{BusState, StripState};
+use service::FULL_REFRESH_MS;
+use vban::{stream_name, MidiPacket, RTPacket, TextPacket, VoiceMeeterChannel, VoiceMeeterCommand, VoiceMeeterParameter};
+use xctrl::{XctrlButton, XctrlButtonType, XctrlDisplay, XctrlDisplayColor, XctrlEncoderRing, XctrlFader, XctrlMessage, XctrlMeter, XctrlState, XCTRL_HANDSHAKE_REPLY};
+
+// Translates between VoiceMeeter and the X-Touch. Sockets, queues and threads stay with the
+// caller, which feeds decoded events in and sends whatever comes back out.
+
+// Something that happened on either side of the bridge
+#[derive(Clone)]
+pub enum BridgeEvent {
+  Vban(Box<RTPacket>),
+  // Index of the device in the list the bridge was built with, and what it sent
+  Xctrl(usize, XctrlMessage),
+  // A new config to apply without restarting
+  Reload(Box<Config>)
+}
+
+impl BridgeEvent {
+  // Only the newest RT packet matters, so a new one makes any still waiting to be handled stale
+  pub fn supersedes(queued: &BridgeEvent, new: &BridgeEvent) -> bool {
+    matches!((queued, new), (BridgeEvent::Vban(_), BridgeEvent::Vban(_)))
+  }
+}
+
+// A datagram for the caller to send, or news for it to pass on
+#[derive(Clone, Debug, PartialEq)]
+pub enum BridgeOutput {
+  Xctrl(String, Vec<u8>),
+  Vban(Vec<u8>),
+  Status(BridgeStatus)
+}
+
+// Things worth telling whoever runs the bridge, which decides how to show them
+#[derive(Clone, Debug, PartialEq)]
+pub enum BridgeStatus {
+  // A surface probed again after going quiet, so all of it is sent afresh
+  SurfaceConnected(String),
+  ConfigReloaded,
+  // The reason the new config was refused, the bridge carries on with the one it has
+  ConfigRefused(String),
+  RestartNeeded,
+  SendsOnFaderNeedsText,
+  SendsOnFaderNeedsGainLayers
+}
+
+impl fmt::Display for BridgeStatus {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      BridgeStatus::SurfaceConnected(ref address) => write!(f, "New connection made to {}, refreshing surface...", address),
+      BridgeStatus::ConfigReloaded => write!(f, "Config reloaded"),
+      BridgeStatus::ConfigRefused(ref reason) => write!(f, "Keeping the last good config, {}", reason),
+      BridgeStatus::RestartNeeded => write!(f, "Addresses, ports, RT stream names and the capture file only change on a restart"),
+      BridgeStatus::SendsOnFaderNeedsText => write!(f, "Sends on fader needs text output mode"),
+      BridgeStatus::SendsOnFaderNeedsGainLayers => write!(f, "Sends on fader needs an edition of VoiceMeeter with gain layers"),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+  Midi,
+  Text
+}
+
+impl OutputMode {
+  pub fn from(val: &str) -> Option<Self> {
+    match val {
+      "midi" => Some(OutputMode::Midi),
+      "text" => Some(OutputMode::Text),
+      _ => None,
+    }
+  }
+}
+
+// What the master fader controls
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MasterFader {
+  Bus(usize),
+  Selected
+}
+
+impl MasterFader {
+  pub fn from(val: &str) -> Option<Self> {
+    match val {
+      "selected" => Some(MasterFader::Selected),
+      _ => match val.parse::<usize>() {
+        Ok(bus) if (1..=8).contains(&bus) => Some(MasterFader::Bus(bus - 1)),
+        _ => None
+      }
+    }
+  }
+}
+
+// In MIDI mode the master fader is sent as channel volume on the last MIDI channel, which no
+// page uses, with the top seven bits of its level
+pub const MASTER_FADER_STATUS: u8 = 0xbf;
+pub const MASTER_FADER_CONTROL: u8 = 0x07;
+
+// A surface on the network, either the X-Touch itself or an Extender
+pub struct XctrlDevice {
+  pub address: String,
+  // How many surfaces this device sits to the right of the main one
+  pub offset: usize,
+  // What the device should be showing, including which of its faders are being touched
+  pub surface: XctrlState,
+  // What the device is actually showing, as far as we know. None forces a full refresh.
+  pub shown: Option<XctrlState>,
+  // Faders let go of since the last RT packet. They stay held like touched ones until VoiceMeeter
+  // reports where they ended up, rather than being pulled back to the level from before the move.
+  pub released: [bool; 9],
+  pub connection_time: SystemTime,
+  pub last_full_refresh: SystemTime
+}
+
+impl XctrlDevice {
+  pub fn new(address: String, offset: usize) -> Self {
+    XctrlDevice {
+      address,
+      offset,
+      surface: XctrlState::new(),
+      shown: None,
+      released: [false; 9],
+      connection_time: SystemTime::now(),
+      last_full_refresh: SystemTime::now()
+    }
+  }
+}
+
+
+fn button_state(on: bool) -> u8 {
+  if on { 127 } else { 0 }
+}
+
+// Strip and bus encoders are kept apart, the bus ones only ever control gain
+fn encoder_bank(channel: VoiceMeeterChannel) -> (usize, usize) {
+  match channel {
+    VoiceMeeterChannel::Strip(strip) => (0, strip),
+    VoiceMeeterChannel::Bus(bus) => (1, bus)
+  }
+}
+
+pub struct Bridge {
+  pub output_mode: OutputMode,
+  pub master_fader: MasterFader,
+  pub devices: Vec<XctrlDevice>,
+  // Which strip or bus each fader shows, page by page. The pages sit end to end, 8 faders each.
+  pub pages: Vec<Page>,
+  // Fader position, across every page, shown on the main surface's first fader
+  pub position: usize,
+  // Bus whose strip gain layer is on the faders, when sends on fader is active
+  pub send_layer: Option<usize>,
+  // What each page should look like, whether or not a device is showing it
+  pub banks: Vec<XctrlState>,
+  // Number of strips and buses the connected VoiceMeeter edition has
+  pub channel_counts: [usize; 2],
+  // Strip encoders follow the assignment buttons, bus encoders always control gain
+  pub encoders: [EncoderState; 2],
+  // Last strip or bus picked with a Select button, for a master fader following the selection
+  pub selected_channel: VoiceMeeterChannel,
+  pub fader_range: FaderRange,
+  pub button_rows: ButtonRows,
+  pub streams: VbanStreams,
+  // The config the bridge is running, for telling which parts of a reload can't be applied live
+  config: Config,
+  // Last RT packet, so a reload can redraw the pages without waiting for the next one
+  last_update: Option<Box<RTPacket>>,
+  frame_id: u32
+}
+
+impl Bridge {
+  // Further surfaces, such as an Extender, follow the first one and carry on the page layout.
+  // A config whose pages don't make sense is refused rather than quietly swapped for the defaults.
+  pub fn new(config: &Config) -> Result<Self, ConfigError> {
+    let mut encoders = [EncoderState::new(), EncoderState::new()];
+    encoders[1].select(XctrlButtonType::Track as u8);
+    let pages = config.pages()?;
+
+    Ok(Bridge {
+      output_mode: config.vban.output,
+      master_fader: config.master_fader(),
+      devices: config.surface_addresses().into_iter().enumerate().map(|(offset, address)| XctrlDevice::new(address, offset)).collect(),
+      banks: pages.iter().map(|_| XctrlState::new()).collect(),
+      pages,
+      position: 0,
+      send_layer: None,
+      channel_counts: [8, 8],
+      encoders,
+      selected_channel: VoiceMeeterChannel::Bus(0),
+      fader_range: config.faders,
+      button_rows: config.buttons,
+      streams: config.vban.streams.clone(),
+      config: config.clone(),
+      last_update: None,
+      frame_id: 0
+    })
+  }
+
+  // Swaps in the pages, colors and mappings from a new config and redraws them. A config that
+  // fails validation is refused and the bridge carries on with the one it has. Addresses, ports
+  // and the RT stream names belong to the sockets and threads, so they only change on a restart.
+  pub fn reload(&mut self, config: &Config) -> Result<(), ConfigError> {
+    config.validate()?;
+    let pages = config.pages()?;
+
+    self.output_mode = config.vban.output;
+    self.master_fader = config.master_fader();
+    self.banks = pages.iter().map(|_| XctrlState::new()).collect();
+    self.pages = pages;
+    self.position = self.position.min(self.last_position());
+    if self.output_mode != OutputMode::Text || !(0..self.pages.len()).any(|page| self.page_has_strips(page)) {
+      self.send_layer = None;
+    }
+    self.fader_range = config.faders;
+    self.button_rows = config.buttons;
+    self.streams.command = config.vban.streams.command.clone();
+    self.streams.midi = config.vban.streams.midi.clone();
+    // Only what was applied is kept, so the addresses in use stay the ones compared against
+    self.config.vban.output = config.vban.output;
+    self.config.vban.master = config.vban.master.clone();
+    self.config.vban.streams.command = config.vban.streams.command.clone();
+    self.config.vban.streams.midi = config.vban.streams.midi.clone();
+    self.config.faders = config.faders;
+    self.config.buttons = config.buttons;
+    self.config.pages = config.pages.clone();
+
+    self.reapply_last_update();
+    Ok(())
+  }
+
+  // Whether a config changes anything reload leaves alone until the next start
+  pub fn needs_restart(&self, config: &Config) -> bool {
+    config.bind != self.config.bind || config.capture != self.config.capture || config.xctrl != self.config.xctrl || config.vban.host != self.config.vban.host || config.vban.port != self.config.vban.port
+      || config.vban.discover != self.config.vban.discover || config.vban.broadcast != self.config.vban.broadcast
+      || config.vban.streams.rt != self.config.vban.streams.rt || config.vban.streams.register != self.config.vban.streams.register
+  }
+
+  // Brings the pages back in line with the last RT packet after a change to what they show
+  fn reapply_last_update(&mut self) {
+    if let Some(update) = self.last_update.take() {
+      self.handle_vban(&update);
+      self.last_update = Some(update);
+    }
+  }
+
+  pub fn handle(&mut self, event: BridgeEvent) -> Vec<BridgeOutput> {
+    self.handle_at(event, SystemTime::now())
+  }
+
+  // As handle, with the time it happened given rather than read from the clock, so a replay
+  // behaves the same however fast it runs
+  pub fn handle_at(&mut self, event: BridgeEvent, now: SystemTime) -> Vec<BridgeOutput> {
+    match event {
+      BridgeEvent::Vban(packet) => {
+        // VoiceMeeter restarted as an edition without that gain layer drops the surface out of sends on fader
+        if matches!(self.send_layer, Some(layer) if layer >= packet.edition().map_or(0, |edition| edition.gain_layers())) {
+          self.send_layer = None;
+        }
+        self.handle_vban(&packet);
+        self.last_update = Some(packet);
+        for xctrl_device in self.devices.iter_mut() {
+          let faders = xctrl_device.surface.touched.iter_mut().zip(xctrl_device.released.iter_mut());
+          for (touched, released) in faders {
+            if *released {
+              *touched = false;
+              *released = false;
+            }
+          }
+        }
+        Vec::new()
+      },
+      BridgeEvent::Xctrl(device, message) => self.handle_xctrl_at(device, message, now),
+      BridgeEvent::Reload(config) => {
+        let restart_needed = self.needs_restart(&config);
+        match self.reload(&config) {
+          Ok(()) if restart_needed => vec![BridgeOutput::Status(BridgeStatus::ConfigReloaded), BridgeOutput::Status(BridgeStatus::RestartNeeded)],
+          Ok(()) => vec![BridgeOutput::Status(BridgeStatus::ConfigReloaded)],
+          Err(e) => vec![BridgeOutput::Status(BridgeStatus::ConfigRefused(e.to_string()))]
+        }
+      }
+    }
+  }
+
+  // The channel under a fader of a page, if the page and the connected edition have one there
+  pub fn channel(&self, bank: usize, index: usize) -> Option<VoiceMeeterChannel> {
+    let channel = self.pages.get(bank)?.channels.get(index).cloned()??;
+    match channel {
+      VoiceMeeterChannel::Strip(strip) if strip < self.channel_counts[0] => Some(channel),
+      VoiceMeeterChannel::Bus(bus) if bus < self.channel_counts[1] => Some(channel),
+      _ => None
+    }
+  }
+
+  // The strip or bus on the master fader, if the connected edition has it
+  fn master_channel(&self) -> Option<VoiceMeeterChannel> {
+    let channel = match self.master_fader {
+      MasterFader::Bus(bus) => VoiceMeeterChannel::Bus(bus),
+      MasterFader::Selected => self.selected_channel
+    };
+    match channel {
+      VoiceMeeterChannel::Strip(strip) if strip < self.channel_counts[0] => Some(channel),
+      VoiceMeeterChannel::Bus(bus) if bus < self.channel_counts[1] => Some(channel),
+      _ => None
+    }
+  }
+
+  fn page_has_strips(&self, bank: usize) -> bool {
+    self.pages.get(bank).map(|page| page.has_strips()).unwrap_or(false)
+  }
+
+  // Furthest along the main surface can go, with the last page filling it
+  fn last_position(&self) -> usize {
+    (self.pages.len() - 1) * 8
+  }
+
+  // Messages for a channel strip become the same message for the page and fader it's showing.
+  // Anything else belongs to the page under the device's first fader.
+  fn locate(first: usize, update: &XctrlMessage) -> (usize, XctrlMessage) {
+    let strip = |id: u8| {
+      let position = first + (id % 8) as usize;
+      (position / 8, (position % 8) as u8)
+    };
+    match *update {
+      XctrlMessage::Fader { id, level } if id < 8 => {
+        let (bank, index) = strip(id);
+        (bank, XctrlMessage::Fader { id: index, level })
+      },
+      XctrlMessage::FaderTouch { id, touched } if id < 8 => {
+        let (bank, index) = strip(id);
+        (bank, XctrlMessage::FaderTouch { id: index, touched })
+      },
+      XctrlMessage::Encoder { id, delta } if id < 8 => {
+        let (bank, index) = strip(id);
+        (bank, XctrlMessage::Encoder { id: index, delta })
+      },
+      XctrlMessage::Button { id, pressed } if id < XctrlButtonType::Track as u8 => {
+        let (bank, index) = strip(id);
+        (bank, XctrlMessage::Button { id: (id & 0xf8) + index, pressed })
+      },
+      _ => (first / 8, update.clone())
+    }
+  }
+
+  pub fn handle_xctrl(&mut self, device: usize, update: XctrlMessage) -> Vec<BridgeOutput> {
+    self.handle_xctrl_at(device, update, SystemTime::now())
+  }
+
+  fn handle_xctrl_at(&mut self, device: usize, update: XctrlMessage, now: SystemTime) -> Vec<BridgeOutput> {
+    let mut out = Vec::new();
+    let first = match self.devices.get(device) {
+      Some(xctrl_device) => self.position + xctrl_device.offset * 8,
+      None => return out
+    };
+    let (bank, local) = Bridge::locate(first, &update);
+
+    match update {
+      XctrlMessage::Probe => {
+        let xctrl_device = &mut self.devices[device];
+        out.push(BridgeOutput::Xctrl(xctrl_device.address.clone(), XCTRL_HANDSHAKE_REPLY.to_vec()));
+
+        let last_connection_diff = now.duration_since(xctrl_device.connection_time).unwrap_or_default();
+        if last_connection_diff.as_secs() > 5 {
+          xctrl_device.shown = None;
+          out.push(BridgeOutput::Status(BridgeStatus::SurfaceConnected(xctrl_device.address.clone())));
+        }
+        xctrl_device.connection_time = now;
+        return out;
+      },
+      XctrlMessage::DeviceId { .. } | XctrlMessage::Unknown(_) => return out,
+      XctrlMessage::FaderTouch { id, touched } if (id as usize) < 9 => {
+        let xctrl_device = &mut self.devices[device];
+        if touched {
+          xctrl_device.surface.touched[id as usize] = true;
+        }
+        xctrl_device.released[id as usize] = !touched;
+      },
+      // A moved fader is physically where the user left it, so once it's let go and the next RT
+      // packet is in the surface diff sends one correction if VoiceMeeter settled somewhere else
+      XctrlMessage::Fader { id, level } => {
+        if let Some(shown) = self.devices[device].shown.as_mut() {
+          if let Some(fader) = shown.faders.get_mut(id as usize) {
+            fader.level = level;
+          }
+        }
+      },
+      _ => {}
+    }
+
+    // From here on the message is about the page it landed on rather than the device
+    let update = local;
+    if let XctrlMessage::Button { id, pressed: true } = update {
+      let send_layer = self.send_layer;
+      out.extend(self.press(bank, id).map(BridgeOutput::Status));
+      // Strip gain encoders and faders swap between strip and layer gains straight away, rather
+      // than carrying the old gains into the first turn before the next RT packet
+      if self.send_layer != send_layer {
+        self.reapply_last_update();
+      }
+    }
+
+    self.frame_id += 1;
+    match self.output_mode {
+      OutputMode::Midi => {
+        // MIDI channels only stretch to the first two pages, whose faders take up every pitch
+        // bend channel between them. The master fader isn't paged, so it has a control of its own.
+        let raw_message = match (&update, update.as_midi()) {
+          (&XctrlMessage::Fader { id: 8, level }, _) => Some([MASTER_FADER_STATUS, MASTER_FADER_CONTROL, (level >> 8).min(0x7f) as u8]),
+          (_, Some(mut raw_message)) if bank < 2 => {
+            raw_message[0] += 0x08 * bank as u8;
+            // Strip encoders move to the controls of their assignment, keeping the ring in step
+            if let XctrlMessage::Encoder { id, .. } = update {
+              if let Some(VoiceMeeterChannel::Strip(_)) = self.channel(bank, id as usize) {
+                raw_message[1] = self.encoders[0].assignment.midi_control() + id;
+              }
+            }
+            self.encoder_command(&update, bank);
+            Some(raw_message)
+          },
+          _ => None
+        };
+        if let Some(raw_message) = raw_message {
+          let mut packet = MidiPacket::new(raw_message, self.frame_id);
+          packet.header.stream_name = stream_name(&self.streams.midi);
+          let vban_midi_update: [u8; 31] = packet.pack().unwrap();
+          out.push(BridgeOutput::Vban(vban_midi_update.to_vec()));
+        }
+      },
+      OutputMode::Text => {
+        let command = match update {
+          XctrlMessage::Fader { id: 8, level } => self.master_channel()
+            .map(|channel| VoiceMeeterCommand::new(channel, VoiceMeeterParameter::Gain(self.fader_range.gain(level)))),
+          _ => self.encoder_command(&update, bank).or_else(|| self.text_command(&update, bank))
+        };
+        if let Some(command) = command {
+          let mut packet = TextPacket::new(&command.as_script(), self.frame_id);
+          packet.header.stream_name = stream_name(&self.streams.command);
+          out.push(BridgeOutput::Vban(packet.as_bytes()));
+        }
+      }
+    }
+    out
+  }
+
+  // Page, selection and encoder assignment buttons. Fader Bank steps a whole page, lining the
+  // surface back up with one if Channel Bank had shifted it, and Channel Bank steps one fader.
+  fn press(&mut self, bank: usize, id: u8) -> Option<BridgeStatus> {
+    let row = id & 0xf8;
+    let channel = self.channel(bank, (id % 8) as usize);
+    let jump = self.pages.iter().position(|page| page.button.map(|button| button.id()) == Some(id));
+    if id == XctrlButtonType::FaderBank as u8 + 1 {
+      self.position = ((self.position / 8 + 1) * 8).min(self.last_position());
+      self.send_layer = None;
+    } else if id == XctrlButtonType::FaderBank as u8 {
+      let page_start = (self.position / 8) * 8;
+      self.position = if page_start < self.position { page_start } else { self.position.saturating_sub(8) };
+      self.send_layer = None;
+    } else if id == XctrlButtonType::ChannelBank as u8 + 1 {
+      self.position = (self.position + 1).min(self.last_position());
+    } else if id == XctrlButtonType::ChannelBank as u8 {
+      self.position = self.position.saturating_sub(1);
+    } else if let Some(page) = jump {
+      self.position = page * 8;
+      self.send_layer = None;
+    } else if row == XctrlButtonType::Select as u8 {
+      if let Some(channel) = channel {
+        self.selected_channel = channel;
+        // Selecting a bus flips the first page with strips over to that bus's gain layer
+        if let VoiceMeeterChannel::Bus(bus) = channel {
+          let gain_layers = self.last_update.as_ref().and_then(|update| update.edition().ok()).map_or(0, |edition| edition.gain_layers());
+          if self.output_mode != OutputMode::Text {
+            return Some(BridgeStatus::SendsOnFaderNeedsText);
+          } else if bus >= gain_layers {
+            return Some(BridgeStatus::SendsOnFaderNeedsGainLayers);
+          } else {
+            if let Some(page) = (0..self.pages.len()).find(|page| self.page_has_strips(*page)) {
+              self.position = page * 8;
+            }
+            self.send_layer = Some(bus);
+          }
+        }
+      }
+    } else if self.page_has_strips(bank) {
+      self.encoders[0].select(id);
+    }
+    None
+  }
+
+  // Turns encoder movement and encoder pushes into VBAN-TEXT commands for the current assignment
+  fn encoder_command(&mut self, update: &XctrlMessage, bank: usize) -> Option<VoiceMeeterCommand> {
+    let index = match *update {
+      XctrlMessage::Encoder { id, .. } => id as usize,
+      XctrlMessage::Button { id, pressed: true } if (id & 0xf8) == XctrlButtonType::Encoder as u8 => (id % 8) as usize,
+      _ => return None
+    };
+    let channel = self.channel(bank, index)?;
+    let (kind, number) = encoder_bank(channel);
+
+    let encoders = &mut self.encoders[kind];
+    let value = match *update {
+      XctrlMessage::Encoder { delta, .. } => encoders.turn(number, delta),
+      _ => encoders.reset(number)
+    };
+    let parameter = match (encoders.assignment, self.send_layer, channel) {
+      (EncoderAssignment::Gain, Some(layer), VoiceMeeterChannel::Strip(_)) => VoiceMeeterParameter::GainLayer(layer, value),
+      (assignment, _, _) => assignment.parameter(value)
+    };
+    Some(VoiceMeeterCommand::new(channel, parameter))
+  }
+
+  fn row_function(&self, row: u8) -> ButtonFunction {
+    match row {
+      row if row == XctrlButtonType::Rec as u8 => self.button_rows.rec,
+      row if row == XctrlButtonType::Solo as u8 => self.button_rows.solo,
+      row if row == XctrlButtonType::Mute as u8 => self.button_rows.mute,
+      _ => ButtonFunction::None
+    }
+  }
+
+  // Works out which VoiceMeeter parameter a fader or button maps to for VBAN-TEXT output
+  fn text_command(&self, update: &XctrlMessage, bank: usize) -> Option<VoiceMeeterCommand> {
+    match *update {
+      XctrlMessage::Fader { id, level } => {
+        let channel = self.channel(bank, id as usize)?;
+        let gain = self.fader_range.gain(level);
+        let parameter = match (self.send_layer, channel) {
+          (Some(layer), VoiceMeeterChannel::Strip(_)) => VoiceMeeterParameter::GainLayer(layer, gain),
+          _ => VoiceMeeterParameter::Gain(gain)
+        };
+        Some(VoiceMeeterCommand::new(channel, parameter))
+      },
+      XctrlMessage::Button { id, pressed: true } => {
+        let channel = self.channel(bank, (id % 8) as usize)?;
+        // Buttons toggle, so the new value is the inverse of what the LED currently shows
+        let active = match self.banks[bank].buttons.get(id as usize) {
+          Some(button) => button.state != 127,
+          None => return None
+        };
+        let parameter = match (self.row_function(id & 0xf8), channel) {
+          (ButtonFunction::Mono, _) => VoiceMeeterParameter::Mono(active),
+          (ButtonFunction::Solo, VoiceMeeterChannel::Strip(_)) => VoiceMeeterParameter::Solo(active),
+          (ButtonFunction::Mute, _) => VoiceMeeterParameter::Mute(active),
+          _ => return None
+        };
+        Some(VoiceMeeterCommand::new(channel, parameter))
+      },
+      _ => None
+    }
+  }
+
+  fn set_button(&mut self, bank: usize, id: usize, on: bool) {
+    self.banks[bank].buttons[id] = XctrlButton { id: id as u8, state: button_state(on) };
+  }
+
+  // Brings every page up to date with VoiceMeeter. Nothing is sent until the next refresh.
+  pub fn handle_vban(&mut self, update: &RTPacket) {
+    self.channel_counts = [update.strip_count(), update.bus_count()];
+
+    let strip_gains = match self.send_layer {
+      Some(layer) => update.input_gain_layer(layer).unwrap_or(update.input_gains()),
+      None => update.input_gains()
+    };
+    let bus_gains = update.output_gains();
+    let labels = [update.strip_labels(), update.bus_labels()];
+    let meters = [update.input_meters(), update.output_meters()];
+    let strip_states = update.strip_states();
+    let bus_states = update.bus_states();
+
+    for i in 0..8 {
+      self.encoders[0].set_value(EncoderAssignment::Gain, i, strip_gains.get(i).cloned().unwrap_or(0.0));
+      self.encoders[1].set_value(EncoderAssignment::Gain, i, bus_gains.get(i).cloned().unwrap_or(0.0));
+    }
+
+    let master_gain = match self.master_channel() {
+      Some(VoiceMeeterChannel::Strip(strip)) => update.input_gains().get(strip).cloned(),
+      Some(VoiceMeeterChannel::Bus(bus)) => update.output_gains().get(bus).cloned(),
+      None => None
+    };
+    let master_level = master_gain.map_or(0, |gain| self.fader_range.level(gain));
+
+    let rows = [(XctrlButtonType::Rec, self.button_rows.rec), (XctrlButtonType::Solo, self.button_rows.solo), (XctrlButtonType::Mute, self.button_rows.mute)];
+    for bank in 0..self.pages.len() {
+      for i in 0..8 {
+        let channel = self.channel(bank, i);
+        let (kind, number) = match channel {
+          Some(channel) => encoder_bank(channel),
+          None => (0, 8)
+        };
+
+        // Channels the page or edition doesn't have are left blank
+        let (top, bottom) = labels[kind].get(number).map_or(([0; 7], [0; 7]), |label| format_label(label));
+        let mut color = self.pages[bank].colors[i];
+        if top == [0; 7] && bottom == [0; 7] {
+          color = XctrlDisplayColor::Off;
+        }
+        self.banks[bank].displays[i] = XctrlDisplay::new(i as u8, color, &top, &bottom);
+
+        let gain = match channel {
+          Some(VoiceMeeterChannel::Strip(strip)) => strip_gains.get(strip).cloned(),
+          Some(VoiceMeeterChannel::Bus(bus)) => bus_gains.get(bus).cloned(),
+          None => None
+        };
+        self.banks[bank].faders[i] = XctrlFader { id: i as u8, level: gain.map_or(0, |gain| self.fader_range.level(gain)) };
+        self.banks[bank].meters[i] = XctrlMeter { id: i as u8, level: meters[kind].get(number).cloned().unwrap_or(0) as u8 };
+
+        let strip_state: StripState = match channel {
+          Some(VoiceMeeterChannel::Strip(strip)) => strip_states.get(strip).cloned().unwrap_or_default(),
+          _ => StripState::default()
+        };
+        let bus_state: BusState = match channel {
+          Some(VoiceMeeterChannel::Bus(bus)) => bus_states.get(bus).cloned().unwrap_or_default(),
+          _ => BusState::default()
+        };
+        for (row, function) in rows.iter() {
+          // Buses have no solo
+          let on = match *function {
+            ButtonFunction::Mute => strip_state.mute() || bus_state.mute(),
+            ButtonFunction::Solo => strip_state.solo(),
+            ButtonFunction::Mono => strip_state.mono() || bus_state.mono(),
+            ButtonFunction::None => false
+          };
+          self.set_button(bank, *row as usize + i, on);
+        }
+
+        let selected = channel.is_some() && match channel {
+          Some(VoiceMeeterChannel::Bus(bus)) if self.send_layer == Some(bus) => true,
+          _ => self.master_fader == MasterFader::Selected && Some(self.selected_channel) == channel
+        };
+        self.set_button(bank, XctrlButtonType::Select as usize + i, selected);
+      }
+
+      self.banks[bank].faders[8] = XctrlFader { id: 8, level: master_level };
+      let send_mode = self.send_layer.is_some() && self.page_has_strips(bank);
+      self.set_button(bank, XctrlButtonType::Flip as usize, send_mode);
+    }
+  }
+
+  // Works out what every device should be showing and returns whatever it isn't showing yet.
+  // Call it regularly, the caller decides how often.
+  pub fn refresh(&mut self) -> Vec<BridgeOutput> {
+    self.refresh_at(SystemTime::now())
+  }
+
+  // As refresh, at a given time
+  pub fn refresh_at(&mut self, now: SystemTime) -> Vec<BridgeOutput> {
+    for bank in 0..self.pages.len() {
+      for i in 0..8 {
+        self.banks[bank].rings[i] = match self.channel(bank, i) {
+          Some(channel) => {
+            let (kind, number) = encoder_bank(channel);
+            let mut ring = self.encoders[kind].ring(number);
+            ring.id = i as u8;
+            ring
+          },
+          None => XctrlEncoderRing::off(i as u8)
+        };
+      }
+      let strips = self.page_has_strips(bank);
+      for button in [XctrlButtonType::Track, XctrlButtonType::Send, XctrlButtonType::Pan, XctrlButtonType::Eq].iter() {
+        let active = strips && self.encoders[0].assignment.button() as u8 == *button as u8;
+        self.set_button(bank, *button as usize, active);
+      }
+    }
+
+    let last_position = self.last_position();
+    let jumped_to = self.pages.get(self.position / 8).and_then(|page| page.button);
+    let mut out = Vec::new();
+    for device in self.devices.iter_mut() {
+      // Each fader shows its place in the pages, faders past the last page have nothing to show
+      let first = self.position + device.offset * 8;
+      let mut surface = match self.banks.get(first / 8) {
+        Some(bank) => bank.clone(),
+        None => XctrlState::blank()
+      };
+      let blank = XctrlState::blank();
+      for i in 0..8 {
+        let position = first + i;
+        surface.copy_strip(self.banks.get(position / 8).unwrap_or(&blank), position % 8, i);
+      }
+
+      // Bank LEDs show which ways there's further to go
+      let buttons = [
+        ((XctrlButtonType::FaderBank as usize), self.position > 0),
+        (XctrlButtonType::FaderBank as usize + 1, self.position < last_position),
+        ((XctrlButtonType::ChannelBank as usize), self.position > 0),
+        (XctrlButtonType::ChannelBank as usize + 1, self.position < last_position)
+      ];
+      for (id, on) in buttons.iter() {
+        surface.buttons[*id] = XctrlButton { id: *id as u8, state: button_state(*on) };
+      }
+      for button in PageButton::all().iter() {
+        let id = button.id() as usize;
+        surface.buttons[id] = XctrlButton { id: id as u8, state: button_state(jumped_to == Some(*button)) };
+      }
+
+      surface.touched = device.surface.touched;
+      device.surface = surface;
+      if now.duration_since(device.last_full_refresh).unwrap_or_default().as_millis() > FULL_REFRESH_MS as u128 {
+        device.shown = None;
+        device.last_full_refresh = now;
+      }
+
+      for group in device.surface.changes(device.shown.as_ref()) {
+        out.push(BridgeOutput::Xctrl(device.address.clone(), group));
+      }
+
+      // Touched faders weren't sent, so the device still shows whatever it did before,
+      // or somewhere unknown that gets corrected once the fader is let go
+      let mut shown = device.surface.clone();
+      for i in 0..9 {
+        if shown.touched[i] {
+          shown.faders[i] = match device.shown.as_ref() {
+            Some(previous) => previous.faders[i].clone(),
+            None => XctrlFader { id: i as u8, level: u16::MAX }
+          };
+        }
+      }
+      device.shown = Some(shown);
+    }
+    out
+  }
+}
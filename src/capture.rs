@@ -0,0 +1,254 @@
+// Records of the datagrams on both of the bridge's links, and replaying them through the bridge.
+//
+// A capture is a text file with one datagram a line: microseconds since the capture started,
+// which link it was on, the address at the other end and the datagram in hex. Lines starting
+// with '#' are comments. Being text, two captures can be compared with any diff tool.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use bridge::{Bridge, BridgeOutput};
+use config::{Config, ConfigError};
+use service::{decode_vban, decode_xctrl, device_index, REFRESH_MS};
+use vban::stream_name;
+
+const HEADER: &str = "# vban_xctrl capture v1";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Link {
+  XctrlIn,
+  XctrlOut,
+  VbanIn,
+  VbanOut
+}
+
+impl Link {
+  pub fn name(&self) -> &'static str {
+    match *self {
+      Link::XctrlIn => "xctrl-in",
+      Link::XctrlOut => "xctrl-out",
+      Link::VbanIn => "vban-in",
+      Link::VbanOut => "vban-out",
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "xctrl-in" => Some(Link::XctrlIn),
+      "xctrl-out" => Some(Link::XctrlOut),
+      "vban-in" => Some(Link::VbanIn),
+      "vban-out" => Some(Link::VbanOut),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+  Io(io::Error),
+  // A line that isn't a datagram, numbered from 1
+  Parse { line: usize, reason: String }
+}
+
+impl fmt::Display for CaptureError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      CaptureError::Io(ref e) => write!(f, "couldn't read the capture: {}", e),
+      CaptureError::Parse { line, ref reason } => write!(f, "capture line {}: {}", line, reason),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureRecord {
+  // Since the capture started
+  pub at: Duration,
+  pub link: Link,
+  // The surface or VoiceMeeter end of the link
+  pub address: String,
+  pub data: Vec<u8>
+}
+
+impl CaptureRecord {
+  pub fn to_line(&self) -> String {
+    format!("{:>12} {:<9} {} {}", self.at.as_micros(), self.link.name(), self.address, hex::encode(&self.data))
+  }
+
+  pub fn parse_line(line: &str) -> Result<CaptureRecord, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() != 4 {
+      return Err(format!("expected 4 fields, found {}", fields.len()));
+    }
+    let at: u64 = fields[0].parse().map_err(|_| format!("'{}' isn't a time in microseconds", fields[0]))?;
+    let link = Link::from_name(fields[1]).ok_or_else(|| format!("'{}' isn't a link", fields[1]))?;
+    let data = hex::decode(fields[3]).map_err(|e| format!("bad hex, {}", e))?;
+    Ok(CaptureRecord { at: Duration::from_micros(at), link, address: fields[2].to_string(), data })
+  }
+}
+
+pub fn format_capture(records: &[CaptureRecord]) -> String {
+  let mut out = format!("{}\n", HEADER);
+  for record in records {
+    out.push_str(&record.to_line());
+    out.push('\n');
+  }
+  out
+}
+
+pub fn parse_capture(text: &str) -> Result<Vec<CaptureRecord>, CaptureError> {
+  let mut records = Vec::new();
+  for (i, line) in text.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let record = CaptureRecord::parse_line(line).map_err(|reason| CaptureError::Parse { line: i + 1, reason })?;
+    records.push(record);
+  }
+  Ok(records)
+}
+
+pub fn load_capture<P: AsRef<Path>>(path: P) -> Result<Vec<CaptureRecord>, CaptureError> {
+  let text = fs::read_to_string(path).map_err(CaptureError::Io)?;
+  parse_capture(&text)
+}
+
+// Somewhere to write records to as they happen, shared by every I/O thread. Each line is written
+// out straight away so a crash doesn't lose the traffic leading up to it.
+#[derive(Clone)]
+pub struct Capture {
+  started: Instant,
+  out: Arc<Mutex<Box<dyn Write + Send>>>
+}
+
+impl Capture {
+  pub fn new(out: Box<dyn Write + Send>) -> io::Result<Capture> {
+    let capture = Capture { started: Instant::now(), out: Arc::new(Mutex::new(out)) };
+    capture.write(HEADER)?;
+    Ok(capture)
+  }
+
+  pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Capture> {
+    Capture::new(Box::new(BufWriter::new(File::create(path)?)))
+  }
+
+  fn write(&self, line: &str) -> io::Result<()> {
+    let mut out = self.out.lock().expect("Capture::write() tried to lock a poisoned mutex");
+    writeln!(out, "{}", line)?;
+    out.flush()
+  }
+
+  pub fn record(&self, link: Link, address: &str, data: &[u8]) {
+    let record = CaptureRecord { at: self.started.elapsed(), link, address: address.to_string(), data: data.to_vec() };
+    if let Err(e) = self.write(&record.to_line()) {
+      eprintln!("Error writing the capture: {:?}", e);
+    }
+  }
+}
+
+// How fast a replay goes: as fast as it can, or in step with the capture's own timing scaled by
+// a factor, 1.0 being as it happened and 2.0 twice as fast
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplaySpeed {
+  Fastest,
+  Scaled(f32)
+}
+
+// Feeds what the surfaces and VoiceMeeter sent in a capture through a fresh bridge, with the
+// same decoding the processor threads use, and returns everything it sent back. The bridge is
+// refreshed every REFRESH_MS of capture time and runs on the capture's clock, so the same
+// capture gives the same output at any speed. The register heartbeat isn't part of the bridge
+// and doesn't appear, nor do the bridge's status reports.
+//
+// Only the decoding and the bridge are replayed, not the threads and queues start_with() sets
+// up around them. Queue overflow, coalesced RT packets, pings and following VoiceMeeter to a new
+// address don't happen here, and everything goes to the configured VoiceMeeter address.
+pub fn replay(config: &Config, records: &[CaptureRecord], speed: ReplaySpeed) -> Result<Vec<CaptureRecord>, ConfigError> {
+  let mut bridge = Bridge::new(config)?;
+  let devices: Vec<String> = bridge.devices.iter().map(|device| device.address.clone()).collect();
+  let rt_stream = stream_name(&bridge.streams.rt);
+  let vban_address = config.vban_address();
+
+  let clock = SystemTime::now();
+  for device in bridge.devices.iter_mut() {
+    device.connection_time = clock;
+    device.last_full_refresh = clock;
+  }
+  let started = Instant::now();
+
+  let mut incoming: Vec<&CaptureRecord> = records.iter().filter(|record| record.link == Link::XctrlIn || record.link == Link::VbanIn).collect();
+  incoming.sort_by_key(|record| record.at);
+
+  let mut out = Vec::new();
+  let mut collect = |at: Duration, outputs: Vec<BridgeOutput>| {
+    for output in outputs {
+      match output {
+        BridgeOutput::Xctrl(address, data) => out.push(CaptureRecord { at, link: Link::XctrlOut, address, data }),
+        BridgeOutput::Vban(data) => out.push(CaptureRecord { at, link: Link::VbanOut, address: vban_address.clone(), data }),
+        BridgeOutput::Status(_) => {}
+      }
+    }
+  };
+
+  let refresh_interval = Duration::from_millis(REFRESH_MS);
+  let mut next_refresh = refresh_interval;
+  let end = incoming.last().map(|record| record.at).unwrap_or_default() + refresh_interval;
+  let mut pending = incoming.into_iter().peekable();
+  loop {
+    let next_record = pending.peek().map(|record| record.at);
+    let at = match next_record {
+      Some(at) if at < next_refresh => at,
+      _ if next_refresh <= end => next_refresh,
+      _ => break
+    };
+    if let ReplaySpeed::Scaled(factor) = speed {
+      let due = Duration::from_secs_f64(at.as_secs_f64() / factor.max(0.001) as f64);
+      if let Some(wait) = due.checked_sub(started.elapsed()) {
+        thread::sleep(wait);
+      }
+    }
+
+    let now = clock + at;
+    if next_record == Some(at) && at < next_refresh {
+      let record = pending.next().unwrap();
+      let events = match record.link {
+        Link::XctrlIn => {
+          let device = record.address.parse::<SocketAddr>().ok().and_then(|src| device_index(&devices, &src));
+          match device {
+            Some(device) => decode_xctrl(device, &record.data).unwrap_or_default(),
+            None => Vec::new()
+          }
+        },
+        _ => decode_vban(&record.data, &rt_stream).ok().and_then(|event| event).into_iter().collect()
+      };
+      for event in events {
+        collect(at, bridge.handle_at(event, now));
+      }
+    } else {
+      collect(at, bridge.refresh_at(now));
+      next_refresh += refresh_interval;
+    }
+  }
+  Ok(out)
+}
+
+// Where two output streams first part ways, for checking a replay against a stored golden copy.
+// Timings are compared as well as the datagrams.
+pub fn first_difference(expected: &[CaptureRecord], actual: &[CaptureRecord]) -> Option<String> {
+  for i in 0..expected.len().max(actual.len()) {
+    match (expected.get(i), actual.get(i)) {
+      (Some(expected), Some(actual)) if expected == actual => continue,
+      (Some(expected), Some(actual)) => return Some(format!("record {} differs\nexpected: {}\n  actual: {}", i + 1, expected.to_line(), actual.to_line())),
+      (Some(expected), None) => return Some(format!("record {} is missing: {}", i + 1, expected.to_line())),
+      (None, Some(actual)) => return Some(format!("record {} is extra: {}", i + 1, actual.to_line())),
+      (None, None) => break
+    };
+  }
+  None
+}
@@ -0,0 +1,383 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+use toml;
+
+use bridge::{MasterFader, OutputMode};
+use fader_law::FaderRange;
+use vban::VoiceMeeterChannel;
+use xctrl::{XctrlButtonType, XctrlDisplayColor};
+
+// Everything the bridge can be told from a TOML file. Sections and keys that are left out take
+// the same defaults as the command line form.
+
+#[derive(Debug)]
+pub enum ConfigError {
+  Io(io::Error),
+  Parse(toml::de::Error),
+  Invalid(String)
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ConfigError::Io(ref e) => write!(f, "couldn't read the config file: {}", e),
+      ConfigError::Parse(ref e) => write!(f, "couldn't parse the config file: {}", e),
+      ConfigError::Invalid(ref reason) => write!(f, "invalid config: {}", reason),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct XctrlConfig {
+  pub port: u16,
+  // IPs of the X-Touch and any Extenders, each one showing the page after the one before it.
+  // An address with its own port uses that port instead.
+  pub surfaces: Vec<String>
+}
+
+impl Default for XctrlConfig {
+  fn default() -> Self {
+    XctrlConfig { port: 10111, surfaces: Vec::new() }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VbanStreams {
+  // RT packets from VoiceMeeter
+  pub rt: String,
+  // Name the bridge registers for RT packets under
+  pub register: String,
+  // VBAN-TEXT commands in text mode
+  pub command: String,
+  // VBAN MIDI in midi mode
+  pub midi: String
+}
+
+impl Default for VbanStreams {
+  fn default() -> Self {
+    VbanStreams {
+      rt: "Voicemeeter-RTP".to_string(),
+      register: "X-Touch meters".to_string(),
+      command: "Command1".to_string(),
+      midi: "MIDI1".to_string()
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VbanConfig {
+  // VoiceMeeter's IP, or "auto" to find it with VBAN pings
+  pub host: String,
+  pub port: u16,
+  // With host "auto", the host name or ping stream name of the VoiceMeeter to use. Left empty,
+  // the first VoiceMeeter to answer.
+  pub discover: String,
+  // Where pings are sent to find VoiceMeeter
+  pub broadcast: String,
+  pub output: OutputMode,
+  // A bus number from 1 to 8, or "selected"
+  pub master: String,
+  pub streams: VbanStreams
+}
+
+impl Default for VbanConfig {
+  fn default() -> Self {
+    VbanConfig {
+      host: String::new(),
+      port: 6980,
+      discover: String::new(),
+      broadcast: "255.255.255.255".to_string(),
+      output: OutputMode::Midi,
+      master: "1".to_string(),
+      streams: VbanStreams::default()
+    }
+  }
+}
+
+// What a row of channel buttons toggles
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ButtonFunction {
+  Mute,
+  Solo,
+  Mono,
+  None
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ButtonRows {
+  pub rec: ButtonFunction,
+  pub solo: ButtonFunction,
+  pub mute: ButtonFunction
+}
+
+impl Default for ButtonRows {
+  fn default() -> Self {
+    ButtonRows { rec: ButtonFunction::Mono, solo: ButtonFunction::Solo, mute: ButtonFunction::Mute }
+  }
+}
+
+// Surface buttons that can jump straight to a page
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageButton {
+  Inputs,
+  Outputs,
+  Buses,
+  Aux
+}
+
+impl PageButton {
+  pub fn all() -> [PageButton; 4] {
+    [PageButton::Inputs, PageButton::Outputs, PageButton::Buses, PageButton::Aux]
+  }
+
+  pub fn id(&self) -> u8 {
+    match *self {
+      PageButton::Inputs => XctrlButtonType::Inputs as u8,
+      PageButton::Outputs => XctrlButtonType::Outputs as u8,
+      PageButton::Buses => XctrlButtonType::Buses as u8,
+      PageButton::Aux => XctrlButtonType::Aux as u8,
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[derive(Default)]
+pub struct PageConfig {
+  pub name: String,
+  // Up to 8 of "strip <n>", "bus <n>" or "none", counting from 1
+  pub faders: Vec<String>,
+  // Display color for each fader, strips are green and buses blue unless set here
+  pub colors: Vec<String>,
+  // One of "inputs", "outputs", "buses" or "aux", to jump here from that button
+  pub button: Option<PageButton>
+}
+
+
+// A page as the bridge uses it, with a channel and color for each of the 8 faders
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page {
+  pub name: String,
+  pub channels: [Option<VoiceMeeterChannel>; 8],
+  pub colors: [XctrlDisplayColor; 8],
+  pub button: Option<PageButton>
+}
+
+impl Page {
+  pub fn strips(name: &str) -> Self {
+    Page::from_channels(name, (0..8).map(|i| Some(VoiceMeeterChannel::Strip(i))).collect())
+  }
+
+  pub fn buses(name: &str) -> Self {
+    Page::from_channels(name, (0..8).map(|i| Some(VoiceMeeterChannel::Bus(i))).collect())
+  }
+
+  fn from_channels(name: &str, channels: Vec<Option<VoiceMeeterChannel>>) -> Self {
+    let mut page = Page { name: name.to_string(), channels: [None; 8], colors: [XctrlDisplayColor::Off; 8], button: None };
+    for (i, channel) in channels.into_iter().take(8).enumerate() {
+      page.channels[i] = channel;
+      page.colors[i] = match channel {
+        Some(VoiceMeeterChannel::Strip(_)) => XctrlDisplayColor::Green,
+        Some(VoiceMeeterChannel::Bus(_)) => XctrlDisplayColor::Blue,
+        None => XctrlDisplayColor::Off
+      };
+    }
+    page
+  }
+
+  pub fn with_button(mut self, button: PageButton) -> Self {
+    self.button = Some(button);
+    self
+  }
+
+  pub fn has_strips(&self) -> bool {
+    self.channels.iter().any(|channel| matches!(channel, Some(VoiceMeeterChannel::Strip(_))))
+  }
+}
+
+fn parse_channel(spec: &str) -> Result<Option<VoiceMeeterChannel>, ConfigError> {
+  let invalid = || ConfigError::Invalid(format!("fader '{}' must be 'strip <1-8>', 'bus <1-8>' or 'none'", spec));
+  let words: Vec<&str> = spec.split_whitespace().collect();
+  if words == ["none"] {
+    return Ok(None);
+  }
+  if words.len() != 2 {
+    return Err(invalid());
+  }
+  let index = match words[1].parse::<usize>() {
+    Ok(index) if (1..=8).contains(&index) => index - 1,
+    _ => return Err(invalid())
+  };
+  match words[0] {
+    "strip" => Ok(Some(VoiceMeeterChannel::Strip(index))),
+    "bus" => Ok(Some(VoiceMeeterChannel::Bus(index))),
+    _ => Err(invalid())
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+  pub bind: String,
+  // Records every datagram on both links to this file, for replaying later
+  pub capture: Option<String>,
+  pub xctrl: XctrlConfig,
+  pub vban: VbanConfig,
+  pub faders: FaderRange,
+  pub buttons: ButtonRows,
+  // Any number of pages, left out the inputs page is followed by the outputs page
+  pub pages: Vec<PageConfig>
+}
+
+impl Default for Config {
+  fn default() -> Self {
+    Config {
+      bind: "0.0.0.0".to_string(),
+      capture: None,
+      xctrl: XctrlConfig::default(),
+      vban: VbanConfig::default(),
+      faders: FaderRange::default(),
+      buttons: ButtonRows::default(),
+      pages: Vec::new()
+    }
+  }
+}
+
+impl Config {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+    let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    Config::parse(&text)
+  }
+
+  pub fn parse(text: &str) -> Result<Config, ConfigError> {
+    let config: Config = toml::from_str(text).map_err(ConfigError::Parse)?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  // The command line shorthand: <machine ip> <xtouch ip[,extender ip...]> <vban ip|auto> [midi|text] [master]
+  pub fn from_args(args: &[String]) -> Result<Config, ConfigError> {
+    if args.len() < 3 || args.len() > 5 {
+      return Err(ConfigError::Invalid("expected <machine ip> <xtouch ip> <vban ip|auto> [midi|text] [master]".to_string()));
+    }
+    let mut config = Config { bind: args[0].clone(), ..Config::default() };
+    config.xctrl.surfaces = args[1].split(',').map(|ip| ip.to_string()).collect();
+    config.vban.host = args[2].clone();
+    if let Some(mode) = args.get(3) {
+      config.vban.output = OutputMode::from(mode).ok_or_else(|| ConfigError::Invalid("output mode must be 'midi' or 'text'".to_string()))?;
+    }
+    if let Some(master) = args.get(4) {
+      config.vban.master = master.clone();
+    }
+    config.validate()?;
+    Ok(config)
+  }
+
+  pub fn validate(&self) -> Result<(), ConfigError> {
+    if self.xctrl.surfaces.is_empty() {
+      return Err(ConfigError::Invalid("at least one X-Touch surface is needed".to_string()));
+    }
+    // Surface input is matched to a surface by the IP it comes from, which a host name never is
+    for surface in self.xctrl.surfaces.iter() {
+      if surface.parse::<IpAddr>().is_err() && surface.parse::<SocketAddr>().is_err() {
+        return Err(ConfigError::Invalid(format!("X-Touch surface '{}' must be an IP address, with or without a port", surface)));
+      }
+    }
+    if self.vban.host.is_empty() {
+      return Err(ConfigError::Invalid("the VoiceMeeter host is missing".to_string()));
+    }
+    if MasterFader::from(&self.vban.master).is_none() {
+      return Err(ConfigError::Invalid("master fader must be a bus number from 1 to 8 or 'selected'".to_string()));
+    }
+    for (name, stream) in [("rt", &self.vban.streams.rt), ("register", &self.vban.streams.register), ("command", &self.vban.streams.command), ("midi", &self.vban.streams.midi)].iter() {
+      if stream.is_empty() || stream.len() > 16 {
+        return Err(ConfigError::Invalid(format!("the {} stream name must be 1 to 16 characters", name)));
+      }
+    }
+    if self.faders.min.partial_cmp(&self.faders.max) != Some(Ordering::Less) {
+      return Err(ConfigError::Invalid("the fader minimum must be below the maximum".to_string()));
+    }
+    self.pages()?;
+    Ok(())
+  }
+
+  pub fn master_fader(&self) -> MasterFader {
+    MasterFader::from(&self.vban.master).unwrap_or(MasterFader::Bus(0))
+  }
+
+  pub fn pages(&self) -> Result<Vec<Page>, ConfigError> {
+    if self.pages.is_empty() {
+      return Ok(vec![Page::strips("Inputs").with_button(PageButton::Inputs), Page::buses("Outputs").with_button(PageButton::Outputs)]);
+    }
+
+    let mut pages = Vec::with_capacity(self.pages.len());
+    for page_config in self.pages.iter() {
+      if page_config.faders.len() > 8 || page_config.colors.len() > 8 {
+        return Err(ConfigError::Invalid(format!("page '{}' has more than 8 faders", page_config.name)));
+      }
+      let channels = page_config.faders.iter().map(|spec| parse_channel(spec)).collect::<Result<Vec<_>, _>>()?;
+      let mut page = Page::from_channels(&page_config.name, channels);
+      for (i, name) in page_config.colors.iter().enumerate() {
+        page.colors[i] = XctrlDisplayColor::from_name(name).ok_or_else(|| ConfigError::Invalid(format!("unknown color '{}'", name)))?;
+      }
+      if page_config.button.is_some() && pages.iter().any(|other: &Page| other.button == page_config.button) {
+        return Err(ConfigError::Invalid(format!("page '{}' uses a button another page already has", page_config.name)));
+      }
+      page.button = page_config.button;
+      pages.push(page);
+    }
+    Ok(pages)
+  }
+
+  pub fn xctrl_bind(&self) -> String {
+    with_port(&self.bind, self.xctrl.port)
+  }
+
+  pub fn vban_bind(&self) -> String {
+    with_port(&self.bind, self.vban.port)
+  }
+
+  pub fn surface_addresses(&self) -> Vec<String> {
+    self.xctrl.surfaces.iter().map(|surface| with_port(surface, self.xctrl.port)).collect()
+  }
+
+  pub fn vban_address(&self) -> String {
+    with_port(&self.vban.host, self.vban.port)
+  }
+
+  // Whether VoiceMeeter is found with pings rather than given by address
+  pub fn discovering(&self) -> bool {
+    self.vban.host == "auto"
+  }
+
+  pub fn vban_broadcast(&self) -> String {
+    with_port(&self.vban.broadcast, self.vban.port)
+  }
+}
+
+// An address with the port added if it doesn't have one. IPv6 addresses are full of colons and
+// need brackets to take a port, so addresses are parsed rather than searched for a colon.
+pub fn with_port(address: &str, port: u16) -> String {
+  if address.parse::<SocketAddr>().is_ok() {
+    return address.to_string();
+  }
+  if let Ok(ip) = address.parse::<IpAddr>() {
+    return SocketAddr::new(ip, port).to_string();
+  }
+  // A host name, which can only have a colon before its port
+  match address.rsplit_once(':') {
+    Some((_, given)) if given.parse::<u16>().is_ok() => address.to_string(),
+    _ => format!("{}:{}", address, port)
+  }
+}
@@ -0,0 +1,164 @@
+// Finding VoiceMeeter on the LAN with the VBAN service ping, and answering pings so the bridge
+// shows up in VoiceMeeter's VBAN panel.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use packed_struct::prelude::*;
+
+use config::Config;
+use vban::*;
+
+// A host that answered a ping
+#[derive(Clone, Debug, PartialEq)]
+pub struct VbanHost {
+  pub address: SocketAddr,
+  // From the header of its answer
+  pub stream: String,
+  pub host_name: String,
+  pub device_name: String,
+  pub application: String,
+  pub version: [u8; 4],
+  pub device_type: u32
+}
+
+impl VbanHost {
+  pub fn from_ping(address: SocketAddr, ping: &PingPacket) -> Self {
+    let stream_end = ping.header.stream_name.iter().position(|byte| *byte == 0).unwrap_or(16);
+    VbanHost {
+      address,
+      stream: String::from_utf8_lossy(&ping.header.stream_name[..stream_end]).to_string(),
+      host_name: ping.body.host_name(),
+      device_name: ping.body.device_name(),
+      application: ping.body.application_name(),
+      version: ping.body.version(),
+      device_type: ping.body.device_type
+    }
+  }
+
+  pub fn is_voicemeeter(&self) -> bool {
+    self.device_type & PING_TYPE_VIRTUAL_MIXER != 0 || self.application.to_lowercase().contains("voicemeeter")
+  }
+}
+
+impl fmt::Display for VbanHost {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let version = self.version.iter().map(|part| part.to_string()).collect::<Vec<String>>().join(".");
+    write!(f, "{} at {}, {} {} ({})", self.host_name, self.address, self.application, version, self.stream)
+  }
+}
+
+// Which VoiceMeeter to use: one on a given host, one answering pings with a given stream name,
+// or with neither set the first to answer
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostSelector {
+  pub name: String
+}
+
+impl HostSelector {
+  pub fn new(name: &str) -> Self {
+    HostSelector { name: name.to_string() }
+  }
+
+  pub fn matches(&self, host: &VbanHost) -> bool {
+    if !host.is_voicemeeter() {
+      return false;
+    }
+    self.name.is_empty() || host.host_name.eq_ignore_ascii_case(&self.name) || host.stream == self.name
+  }
+
+  // Once a host has been picked, the bridge keeps to that machine wherever its address moves
+  pub fn pinned(&self, host: &VbanHost) -> Self {
+    if self.name.is_empty() {
+      return HostSelector::new(&host.host_name);
+    }
+    self.clone()
+  }
+}
+
+// Finding VoiceMeeter while the bridge runs: pings go out to the broadcast address every so often,
+// and the first matching host to answer from a new address is where VBAN goes from then on
+#[derive(Clone, Debug, PartialEq)]
+pub struct Discovery {
+  pub selector: HostSelector,
+  pub broadcast: String
+}
+
+impl Discovery {
+  // Only when the config asks for VoiceMeeter to be found rather than giving its address
+  pub fn from_config(config: &Config) -> Option<Discovery> {
+    if !config.discovering() {
+      return None;
+    }
+    Some(Discovery { selector: HostSelector::new(&config.vban.discover), broadcast: config.vban_broadcast() })
+  }
+}
+
+// The name of the machine the bridge runs on, as far as can be told without asking the OS
+pub fn host_name() -> String {
+  for variable in ["HOSTNAME", "COMPUTERNAME"].iter() {
+    if let Ok(name) = env::var(variable) {
+      if !name.is_empty() {
+        return name;
+      }
+    }
+  }
+  if let Ok(name) = fs::read_to_string("/etc/hostname") {
+    if !name.trim().is_empty() {
+      return name.trim().to_string();
+    }
+  }
+  "vban_xctrl".to_string()
+}
+
+// How the bridge describes itself: it takes RT packets in and sends MIDI or text back out
+pub fn bridge_identity() -> VBANPing0 {
+  let mut version = [0; 4];
+  for (i, part) in env!("CARGO_PKG_VERSION").split('.').take(4).enumerate() {
+    version[i] = part.parse().unwrap_or(0);
+  }
+  VBANPing0::new(PING_TYPE_RECEPTOR | PING_TYPE_TRANSMITTER, PING_FEATURE_MIDI | PING_FEATURE_TXT, version)
+    .with_names("X-Touch bridge", "vban_xctrl", "vban_xctrl", &host_name())
+}
+
+pub fn ping_request(identity: &VBANPing0, frame_id: u32) -> Vec<u8> {
+  PingPacket::new(*identity, false, frame_id).pack().expect("couldn't pack the ping").to_vec()
+}
+
+pub fn ping_reply(identity: &VBANPing0, frame_id: u32) -> Vec<u8> {
+  PingPacket::new(*identity, true, frame_id).pack().expect("couldn't pack the ping").to_vec()
+}
+
+// Broadcasts one ping and lists every host that answers within the wait, in the order they
+// answered. The socket's read timeout is put back to blocking afterwards.
+pub fn discover(socket: &UdpSocket, broadcast: &str, wait: Duration) -> io::Result<Vec<VbanHost>> {
+  socket.set_broadcast(true)?;
+  socket.send_to(&ping_request(&bridge_identity(), 1), broadcast)?;
+
+  let mut hosts: Vec<VbanHost> = Vec::new();
+  let deadline = Instant::now() + wait;
+  let mut buf = [0; 2048];
+  loop {
+    let remaining = match deadline.checked_duration_since(Instant::now()) {
+      Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+      _ => break
+    };
+    socket.set_read_timeout(Some(remaining))?;
+    let (amt, src) = match socket.recv_from(&mut buf) {
+      Ok(received) => received,
+      Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => break,
+      Err(e) => return Err(e)
+    };
+    if let Ok(ping) = PingPacket::parse(&buf[..amt]) {
+      if ping.is_reply() && !hosts.iter().any(|host| host.address == src) {
+        hosts.push(VbanHost::from_ping(src, &ping));
+      }
+    }
+  }
+  socket.set_read_timeout(None)?;
+  Ok(hosts)
+}
@@ -0,0 +1,140 @@
+// A software X-Touch: keeps track of what the bridge has told a surface to show, and makes the
+// messages a surface sends when it's used.
+
+use xctrl::{XctrlButtonType, XctrlMessage, XctrlState, XCTRL_HANDSHAKE_REPLY, XCTRL_PROBE};
+
+// Fader travel, bottom to top
+const FADER_TOP: u16 = 32767;
+
+pub struct XtouchEmulator {
+  // Everything the bridge has set the surface to show
+  pub surface: XctrlState,
+  // Whether the bridge has answered a probe
+  pub connected: bool,
+  // Messages from the bridge that made no sense to a surface
+  pub unknown: usize
+}
+
+impl Default for XtouchEmulator {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl XtouchEmulator {
+  pub fn new() -> Self {
+    XtouchEmulator { surface: XctrlState::blank(), connected: false, unknown: 0 }
+  }
+
+  // What a surface sends every couple of seconds to find the bridge and stay connected
+  pub fn probe(&self) -> Vec<u8> {
+    XCTRL_PROBE.to_vec()
+  }
+
+  // Applies a datagram from the bridge. Everything is sent as SysEx framed messages, several of
+  // which can share a datagram.
+  pub fn apply(&mut self, buf: &[u8]) {
+    let mut offset = 0;
+    while offset < buf.len() {
+      let length = match Self::message_length(&buf[offset..]) {
+        Some(length) => length,
+        None => {
+          self.unknown += 1;
+          return;
+        }
+      };
+      self.apply_message(&buf[offset..(offset + length)]);
+      offset += length;
+    }
+  }
+
+  // Fader levels and display text can hold an end byte of their own, so messages with a fixed
+  // size are taken at that size and only the rest run to the first end byte
+  fn message_length(buf: &[u8]) -> Option<usize> {
+    if buf[0] != 0xf0 {
+      return None;
+    }
+    let length = match buf.get(1..5) {
+      Some([0xd0, ..]) => 4,
+      Some([0xb0, ..]) | Some([0x90, ..]) => 5,
+      Some([status, ..]) if status & 0xf0 == 0xe0 => 5,
+      Some([0x00, 0x00, 0x66, 0x58]) => 22,
+      _ => return buf.iter().position(|byte| *byte == 0xf7).map(|end| end + 1)
+    };
+    if buf.len() < length {
+      return None;
+    }
+    Some(length)
+  }
+
+  fn apply_message(&mut self, message: &[u8]) {
+    let surface = &mut self.surface;
+    match *message {
+      ref reply if *reply == XCTRL_HANDSHAKE_REPLY => self.connected = true,
+      [0xf0, 0x00, 0x00, 0x66, 0x58, id, color, ref text @ .., 0xf7] if (0x20..0x28).contains(&id) && text.len() == 14 => {
+        let display = &mut surface.displays[(id - 0x20) as usize];
+        display.color = color;
+        display.top_text.copy_from_slice(&text[..7]);
+        display.bottom_text.copy_from_slice(&text[7..]);
+      },
+      [0xf0, 0xd0, value, 0xf7] if value >> 4 < 8 => surface.meters[(value >> 4) as usize].level = (value & 0x0f) * 2,
+      [0xf0, status, lower, upper, 0xf7] if status & 0xf0 == 0xe0 && status & 0x0f < 9 => {
+        surface.faders[(status & 0x0f) as usize].level = ((upper as u16) << 8) + lower as u16;
+      },
+      [0xf0, 0xb0, control, value, 0xf7] if (0x30..0x38).contains(&control) => {
+        let ring = &mut surface.rings[(control - 0x30) as usize];
+        ring.center = value & 0x40 == 0x40;
+        ring.mode = value & 0x30;
+        ring.value = value & 0x0f;
+      },
+      [0xf0, 0x90, id, state, 0xf7] if (id as usize) < surface.buttons.len() => surface.buttons[id as usize].state = state,
+      _ => self.unknown += 1
+    };
+  }
+
+  pub fn fader_level(&self, fader: usize) -> u16 {
+    self.surface.faders.get(fader).map(|fader| fader.level).unwrap_or(0)
+  }
+
+  // Grabbing a fader, moving it by a step of its travel either way and letting go. The fader
+  // stays where it was moved to until the bridge says otherwise.
+  pub fn nudge_fader(&mut self, fader: usize, delta: i32) -> Vec<u8> {
+    let level = (self.fader_level(fader) as i32 + delta).clamp(0, FADER_TOP as i32) as u16;
+    self.move_fader(fader, level)
+  }
+
+  pub fn move_fader(&mut self, fader: usize, level: u16) -> Vec<u8> {
+    if fader >= self.surface.faders.len() {
+      return Vec::new();
+    }
+    let id = fader as u8;
+    let level = level.min(FADER_TOP);
+    self.surface.faders[fader].level = level;
+    Self::messages(&[
+      XctrlMessage::FaderTouch { id, touched: true },
+      XctrlMessage::Fader { id, level },
+      XctrlMessage::FaderTouch { id, touched: false }
+    ])
+  }
+
+  // Pressing and releasing a button. Which LEDs light up is left to the bridge.
+  pub fn press(&self, id: u8) -> Vec<u8> {
+    Self::messages(&[XctrlMessage::Button { id, pressed: true }, XctrlMessage::Button { id, pressed: false }])
+  }
+
+  pub fn press_strip(&self, row: XctrlButtonType, strip: usize) -> Vec<u8> {
+    self.press(row as u8 + strip as u8)
+  }
+
+  pub fn turn_encoder(&self, encoder: usize, delta: i8) -> Vec<u8> {
+    Self::messages(&[XctrlMessage::Encoder { id: encoder as u8, delta }])
+  }
+
+  pub fn button_lit(&self, id: u8) -> bool {
+    self.surface.buttons.get(id as usize).map(|button| button.state != 0).unwrap_or(false)
+  }
+
+  fn messages(messages: &[XctrlMessage]) -> Vec<u8> {
+    messages.iter().filter_map(|message| message.as_midi()).flat_map(|bytes| bytes.to_vec()).collect()
+  }
+}
@@ -0,0 +1,161 @@
+use vban::VoiceMeeterParameter;
+use xctrl::{XctrlButtonType, XctrlEncoderRing, XctrlEncoderRingMode};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EncoderAssignment {
+  Gain,
+  Pan,
+  EqBass,
+  EqMid,
+  EqTreble,
+  Reverb,
+  Delay
+}
+
+impl EncoderAssignment {
+  fn index(&self) -> usize {
+    *self as usize
+  }
+
+  // Minimum, maximum and default, in VoiceMeeter's own units
+  pub fn range(&self) -> (f32, f32, f32) {
+    match *self {
+      EncoderAssignment::Gain => (-60.0, 12.0, 0.0),
+      EncoderAssignment::Pan => (-0.5, 0.5, 0.0),
+      EncoderAssignment::EqBass | EncoderAssignment::EqMid | EncoderAssignment::EqTreble => (-12.0, 12.0, 0.0),
+      EncoderAssignment::Reverb | EncoderAssignment::Delay => (0.0, 10.0, 0.0),
+    }
+  }
+
+  // Change per encoder detent
+  pub fn step(&self) -> f32 {
+    match *self {
+      EncoderAssignment::Pan => 0.02,
+      _ => 0.5,
+    }
+  }
+
+  pub fn ring_mode(&self) -> XctrlEncoderRingMode {
+    match *self {
+      EncoderAssignment::Gain => XctrlEncoderRingMode::Wrap,
+      EncoderAssignment::Pan => XctrlEncoderRingMode::SingleDot,
+      EncoderAssignment::EqBass | EncoderAssignment::EqMid | EncoderAssignment::EqTreble => XctrlEncoderRingMode::BoostCut,
+      EncoderAssignment::Reverb | EncoderAssignment::Delay => XctrlEncoderRingMode::Wrap,
+    }
+  }
+
+  pub fn parameter(&self, value: f32) -> VoiceMeeterParameter {
+    match *self {
+      EncoderAssignment::Gain => VoiceMeeterParameter::Gain(value),
+      EncoderAssignment::Pan => VoiceMeeterParameter::Pan(value),
+      EncoderAssignment::EqBass => VoiceMeeterParameter::EqGain(0, value),
+      EncoderAssignment::EqMid => VoiceMeeterParameter::EqGain(1, value),
+      EncoderAssignment::EqTreble => VoiceMeeterParameter::EqGain(2, value),
+      EncoderAssignment::Reverb => VoiceMeeterParameter::Reverb(value),
+      EncoderAssignment::Delay => VoiceMeeterParameter::Delay(value),
+    }
+  }
+
+  // First of the eight controls strip encoders send on in MIDI mode, so VoiceMeeter's MIDI mapping
+  // can tell the assignments apart. Pan keeps the controls the X-Touch itself uses, the rest sit
+  // in blocks of eight above everything else the surface sends.
+  pub fn midi_control(&self) -> u8 {
+    match *self {
+      EncoderAssignment::Pan => 0x10,
+      EncoderAssignment::Gain => 0x40,
+      EncoderAssignment::EqBass => 0x48,
+      EncoderAssignment::EqMid => 0x50,
+      EncoderAssignment::EqTreble => 0x58,
+      EncoderAssignment::Reverb => 0x60,
+      EncoderAssignment::Delay => 0x68,
+    }
+  }
+
+  // The assignment button whose LED shows this assignment is active
+  pub fn button(&self) -> XctrlButtonType {
+    match *self {
+      EncoderAssignment::Gain => XctrlButtonType::Track,
+      EncoderAssignment::Pan => XctrlButtonType::Pan,
+      EncoderAssignment::EqBass | EncoderAssignment::EqMid | EncoderAssignment::EqTreble => XctrlButtonType::Eq,
+      EncoderAssignment::Reverb | EncoderAssignment::Delay => XctrlButtonType::Send,
+    }
+  }
+}
+
+// Per-strip values behind the encoders. Gains are read from every RT packet. The packet has
+// nothing for pan, EQ or sends, so those are tracked from what the surface has sent and start at
+// VoiceMeeter's defaults.
+pub struct EncoderState {
+  pub assignment: EncoderAssignment,
+  values: [[f32; 8]; 7]
+}
+
+impl Default for EncoderState {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl EncoderState {
+  pub fn new() -> Self {
+    let mut values = [[0.0; 8]; 7];
+    for assignment in [EncoderAssignment::Gain, EncoderAssignment::Pan, EncoderAssignment::EqBass, EncoderAssignment::EqMid, EncoderAssignment::EqTreble, EncoderAssignment::Reverb, EncoderAssignment::Delay].iter() {
+      values[assignment.index()] = [assignment.range().2; 8];
+    }
+    EncoderState {
+      assignment: EncoderAssignment::Pan,
+      values
+    }
+  }
+
+  // Handles a press of an assignment button, pressing Eq or Send again steps through their options.
+  // Returns false if the button isn't an assignment button.
+  pub fn select(&mut self, button_id: u8) -> bool {
+    self.assignment = match button_id {
+      id if id == XctrlButtonType::Track as u8 => EncoderAssignment::Gain,
+      id if id == XctrlButtonType::Pan as u8 => EncoderAssignment::Pan,
+      id if id == XctrlButtonType::Eq as u8 => match self.assignment {
+        EncoderAssignment::EqBass => EncoderAssignment::EqMid,
+        EncoderAssignment::EqMid => EncoderAssignment::EqTreble,
+        _ => EncoderAssignment::EqBass
+      },
+      id if id == XctrlButtonType::Send as u8 => match self.assignment {
+        EncoderAssignment::Reverb => EncoderAssignment::Delay,
+        _ => EncoderAssignment::Reverb
+      },
+      _ => return false
+    };
+    true
+  }
+
+  pub fn value(&self, assignment: EncoderAssignment, strip: usize) -> f32 {
+    self.values[assignment.index()][strip]
+  }
+
+  pub fn set_value(&mut self, assignment: EncoderAssignment, strip: usize, value: f32) {
+    let (min, max, _) = assignment.range();
+    self.values[assignment.index()][strip] = value.clamp(min, max);
+  }
+
+  // Applies an encoder movement to the current assignment and returns the new value
+  pub fn turn(&mut self, strip: usize, delta: i8) -> f32 {
+    let assignment = self.assignment;
+    let value = self.value(assignment, strip) + (delta as f32 * assignment.step());
+    self.set_value(assignment, strip, value);
+    self.value(assignment, strip)
+  }
+
+  // Puts the current assignment back to its default and returns it
+  pub fn reset(&mut self, strip: usize) -> f32 {
+    let assignment = self.assignment;
+    self.set_value(assignment, strip, assignment.range().2);
+    self.value(assignment, strip)
+  }
+
+  pub fn ring(&self, strip: usize) -> XctrlEncoderRing {
+    let assignment = self.assignment;
+    let (min, max, _) = assignment.range();
+    let position = (self.value(assignment, strip) - min) / (max - min);
+    XctrlEncoderRing::new(strip as u8, assignment.ring_mode(), position)
+  }
+}
@@ -0,0 +1,150 @@
+// Maps between where a fader sits and the gain it stands for, the same both ways so a level
+// sent from a fader and the motor position for that gain agree. Fader levels run from 0 at the
+// bottom of travel to 32767 at the top.
+
+const FADER_TOP: f32 = 32767.0;
+
+// Travel and gain at each point along the audio curve, straight lines in between. Over the
+// default range unity sits three quarters of the way up and half the travel covers -20 to +6 dB.
+const AUDIO_CURVE: [(f32, f32); 9] = [
+  (0.0, -60.0),
+  (0.125, -40.0),
+  (0.25, -30.0),
+  (0.375, -20.0),
+  (0.5, -12.0),
+  (0.625, -6.0),
+  (0.75, 0.0),
+  (0.875, 6.0),
+  (1.0, 12.0)
+];
+
+// dB of gain for each tenfold step in travel on the amplitude curve, travel following the
+// fourth root of the signal's amplitude
+const AMPLITUDE_DB_PER_DECADE: f32 = 80.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FaderCurve {
+  // Gain in a straight line from the bottom of the range to the top
+  Linear,
+  // A console style taper with unity at the three quarter mark. Other ranges scale the parts of
+  // the curve above and below unity separately, so it stays on the mark.
+  Audio,
+  // Travel follows the fourth root of amplitude, from the top of the range down
+  Amplitude
+}
+
+// Gain at the bottom and top of fader travel, in dB, and the curve between them. Every curve
+// stretches to fill the range, the audio curve keeping unity where the surface marks it.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FaderRange {
+  pub min: f32,
+  pub max: f32,
+  pub curve: FaderCurve
+}
+
+impl Default for FaderRange {
+  fn default() -> Self {
+    FaderRange { min: -60.0, max: 12.0, curve: FaderCurve::Audio }
+  }
+}
+
+impl FaderRange {
+  pub fn level(&self, gain: f32) -> u16 {
+    (self.position(gain) * FADER_TOP).round() as u16
+  }
+
+  pub fn gain(&self, level: u16) -> f32 {
+    self.gain_at(level.min(FADER_TOP as u16) as f32 / FADER_TOP)
+  }
+
+  // How far up its travel, from 0.0 to 1.0, the fader sits for a gain
+  pub fn position(&self, gain: f32) -> f32 {
+    let gain = gain.max(self.min).min(self.max);
+    let position = match self.curve {
+      FaderCurve::Linear => (gain - self.min) / (self.max - self.min),
+      FaderCurve::Audio => {
+        let gain = self.audio_curve_gain(gain);
+        let mut position = 1.0;
+        for pair in AUDIO_CURVE.windows(2) {
+          let ((from_position, from_gain), (to_position, to_gain)) = (pair[0], pair[1]);
+          if gain <= to_gain {
+            position = from_position + ((gain - from_gain).max(0.0) / (to_gain - from_gain)) * (to_position - from_position);
+            break;
+          }
+        }
+        position
+      },
+      FaderCurve::Amplitude => {
+        let floor = self.amplitude_floor();
+        (amplitude_position(gain - self.max) - floor) / (1.0 - floor)
+      }
+    };
+    position.clamp(0.0, 1.0)
+  }
+
+  // Gain for a fader sitting a fraction of the way up its travel
+  pub fn gain_at(&self, position: f32) -> f32 {
+    let position = position.clamp(0.0, 1.0);
+    let gain = match self.curve {
+      FaderCurve::Linear => self.min + position * (self.max - self.min),
+      FaderCurve::Audio => {
+        let mut gain = AUDIO_CURVE[AUDIO_CURVE.len() - 1].1;
+        for pair in AUDIO_CURVE.windows(2) {
+          let ((from_position, from_gain), (to_position, to_gain)) = (pair[0], pair[1]);
+          if position <= to_position {
+            gain = from_gain + ((position - from_position) / (to_position - from_position)) * (to_gain - from_gain);
+            break;
+          }
+        }
+        self.range_gain(gain)
+      },
+      FaderCurve::Amplitude => {
+        let floor = self.amplitude_floor();
+        self.max + AMPLITUDE_DB_PER_DECADE * (floor + position * (1.0 - floor)).log10()
+      }
+    };
+    gain.max(self.min).min(self.max)
+  }
+
+  // Where a gain in the range falls on the audio curve's own scale. Cuts and boosts scale
+  // separately so 0 dB stays at unity, unless the range doesn't reach both sides of it and the
+  // whole curve has to stretch instead.
+  fn audio_curve_gain(&self, gain: f32) -> f32 {
+    let (bottom, top) = (AUDIO_CURVE[0].1, AUDIO_CURVE[AUDIO_CURVE.len() - 1].1);
+    if !self.spans_unity() {
+      bottom + (gain - self.min) / (self.max - self.min) * (top - bottom)
+    } else if gain < 0.0 {
+      gain / self.min * bottom
+    } else {
+      gain / self.max * top
+    }
+  }
+
+  // The other way, from the audio curve's scale back to a gain in the range
+  fn range_gain(&self, curve_gain: f32) -> f32 {
+    let (bottom, top) = (AUDIO_CURVE[0].1, AUDIO_CURVE[AUDIO_CURVE.len() - 1].1);
+    if !self.spans_unity() {
+      self.min + (curve_gain - bottom) / (top - bottom) * (self.max - self.min)
+    } else if curve_gain < 0.0 {
+      curve_gain / bottom * self.min
+    } else {
+      curve_gain / top * self.max
+    }
+  }
+
+  fn spans_unity(&self) -> bool {
+    self.min < 0.0 && self.max > 0.0
+  }
+
+  // Where the bottom of the range would sit on the unstretched amplitude curve
+  fn amplitude_floor(&self) -> f32 {
+    amplitude_position(self.min - self.max)
+  }
+}
+
+// Travel on the unstretched amplitude curve for a gain relative to the top of the range
+fn amplitude_position(relative_gain: f32) -> f32 {
+  10f32.powf(relative_gain / AMPLITUDE_DB_PER_DECADE)
+}
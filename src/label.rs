@@ -0,0 +1,117 @@
+// Fits a VoiceMeeter label onto a scribble strip: two lines of 7 characters from the plain ASCII
+// the display can show. Unused space is left as NULs, which the display shows blank.
+
+const LINE_LENGTH: usize = 7;
+
+// The nearest ASCII for characters VoiceMeeter labels are likely to have. Anything else non-ASCII
+// shows as '?', so it's clear something is there.
+fn transliterate(c: char) -> &'static str {
+  match c {
+    'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => "a",
+    'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' => "A",
+    'æ' => "ae",
+    'Æ' => "AE",
+    'ç' | 'ć' | 'č' => "c",
+    'Ç' | 'Ć' | 'Č' => "C",
+    'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => "e",
+    'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ę' | 'Ě' => "E",
+    'ì' | 'í' | 'î' | 'ï' => "i",
+    'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+    'ł' => "l",
+    'Ł' => "L",
+    'ñ' | 'ń' | 'ň' => "n",
+    'Ñ' | 'Ń' | 'Ň' => "N",
+    'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => "o",
+    'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' => "O",
+    'œ' => "oe",
+    'Œ' => "OE",
+    'ř' => "r",
+    'Ř' => "R",
+    'ś' | 'š' => "s",
+    'Ś' | 'Š' => "S",
+    'ß' => "ss",
+    'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' => "u",
+    'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ū' | 'Ů' => "U",
+    'ý' | 'ÿ' => "y",
+    'Ý' | 'Ÿ' => "Y",
+    'ź' | 'ż' | 'ž' => "z",
+    'Ź' | 'Ż' | 'Ž' => "Z",
+    '‘' | '’' | '′' => "'",
+    '“' | '”' => "\"",
+    '‐' | '‑' | '–' | '—' | '−' => "-",
+    '…' => "...",
+    '×' => "x",
+    _ => "?"
+  }
+}
+
+// Plain ASCII, with control characters and runs of whitespace turned into single spaces
+fn to_ascii(label: &str) -> String {
+  let mut out = String::with_capacity(label.len());
+  for c in label.chars() {
+    if c.is_whitespace() || c.is_control() {
+      out.push(' ');
+    } else if c.is_ascii() {
+      out.push(c);
+    } else {
+      out.push_str(transliterate(c));
+    }
+  }
+  out.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn is_vowel(c: u8) -> bool {
+  b"aeiou".contains(&c)
+}
+
+// Shortens text to fit a line by dropping lowercase vowels from the ends of words, last first
+// and never the first letter of a word, then the spaces, then whatever still doesn't fit
+fn abbreviate(text: &str) -> Vec<u8> {
+  let mut bytes = text.as_bytes().to_vec();
+  let mut i = bytes.len();
+  while bytes.len() > LINE_LENGTH && i > 1 {
+    i -= 1;
+    if is_vowel(bytes[i]) && bytes[i - 1] != b' ' {
+      bytes.remove(i);
+    }
+  }
+  if bytes.len() > LINE_LENGTH {
+    bytes.retain(|c| *c != b' ');
+  }
+  bytes.truncate(LINE_LENGTH);
+  bytes
+}
+
+fn line(text: &[u8]) -> [u8; LINE_LENGTH] {
+  let mut out = [0; LINE_LENGTH];
+  let length = text.len().min(LINE_LENGTH);
+  out[..length].copy_from_slice(&text[..length]);
+  out
+}
+
+// The top and bottom line for a label. Short labels take the top line only. Longer ones split
+// between words, with as many words on the top line as fit, and are abbreviated when they still
+// don't fit. A single long word runs on to the bottom line if it fits across both.
+pub fn format_label(label: &str) -> ([u8; LINE_LENGTH], [u8; LINE_LENGTH]) {
+  let label = to_ascii(label.split('\0').next().unwrap_or(""));
+  if label.len() <= LINE_LENGTH {
+    return (line(label.as_bytes()), [0; LINE_LENGTH]);
+  }
+
+  let words: Vec<&str> = label.split(' ').collect();
+  if words.len() == 1 {
+    if label.len() <= LINE_LENGTH * 2 {
+      let bytes = label.as_bytes();
+      return (line(&bytes[..LINE_LENGTH]), line(&bytes[LINE_LENGTH..]));
+    }
+    return (line(&abbreviate(&label)), [0; LINE_LENGTH]);
+  }
+
+  let mut split = 1;
+  while split < words.len() && words[..(split + 1)].join(" ").len() <= LINE_LENGTH {
+    split += 1;
+  }
+  let top = words[..split].join(" ");
+  let bottom = words[split..].join(" ");
+  (line(&abbreviate(&top)), line(&abbreviate(&bottom)))
+}
@@ -1,9 +1,42 @@
+extern crate hex;
 extern crate packed_struct;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 
+mod bridge;
+mod capture;
+mod config;
+mod discovery;
+#[cfg(feature = "emulator")]
+mod emulator;
+mod encoder;
+mod fader_law;
+mod label;
 mod queue;
+mod rt_state;
+mod service;
+#[cfg(feature = "simulator")]
+mod simulator;
+mod transport;
 mod vban;
 mod xctrl;
 
-pub use crate::queue::WorkQueue;
+pub use crate::bridge::*;
+pub use crate::capture::*;
+pub use crate::config::*;
+pub use crate::discovery::*;
+#[cfg(feature = "emulator")]
+pub use crate::emulator::*;
+pub use crate::encoder::*;
+pub use crate::fader_law::*;
+pub use crate::label::*;
+pub use crate::queue::{OverflowPolicy, WorkQueue};
+pub use crate::rt_state::*;
+pub use crate::service::{decode_vban, decode_xctrl, start, start_watching, start_with, StartOptions};
+#[cfg(feature = "simulator")]
+pub use crate::simulator::*;
+pub use crate::transport::*;
 pub use crate::vban::*;
 pub use crate::xctrl::*;
@@ -1,33 +1,138 @@
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::sync::Condvar;
 use std::sync::Mutex;
+use std::time::Duration;
 
 // Shamelessly stolen from https://gist.github.com/NoraCodes/e6d40782b05dc8ac40faf3a0405debd3
 
+// What a bounded queue does with new work once it is full
+#[derive(Clone, Copy)]
+pub enum OverflowPolicy<T> {
+    DropOldest,
+    DropNewest,
+    // New work takes the place of a queued item the function says it supersedes, whether or not
+    // the queue is full. Otherwise it waits for space like Block, so nothing is thrown away.
+    Coalesce(fn(&T, &T) -> bool),
+    Block,
+}
+
+struct Inner<T> {
+    queue: Mutex<VecDeque<T>>,
+    available: Condvar,
+    space: Condvar,
+    capacity: Option<usize>,
+    policy: OverflowPolicy<T>,
+    dropped: AtomicUsize,
+}
+
 #[derive(Clone)]
 pub struct WorkQueue<T: Send + Clone> {
-    inner: Arc<Mutex<VecDeque<T>>>,
+    inner: Arc<Inner<T>>,
 }
 
 impl<T: Send + Clone> WorkQueue<T> {
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
+        Self::with_capacity(None, OverflowPolicy::Block)
+    }
+
+    pub fn bounded(capacity: usize, policy: OverflowPolicy<T>) -> Self {
+        Self::with_capacity(Some(capacity), policy)
+    }
+
+    fn with_capacity(capacity: Option<usize>, policy: OverflowPolicy<T>) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(VecDeque::new())),
+            inner: Arc::new(Inner {
+                queue: Mutex::new(VecDeque::new()),
+                available: Condvar::new(),
+                space: Condvar::new(),
+                capacity,
+                policy,
+                dropped: AtomicUsize::new(0),
+            }),
         }
     }
 
+    // Number of items thrown away because the queue was full
+    pub fn dropped(&self) -> usize {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.queue.lock().expect("WorkQueue::len() tried to lock a poisoned mutex").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn get_work(&self) -> Option<T> {
-        let maybe_queue = self.inner.lock();
+        let maybe_queue = self.inner.queue.lock();
         if let Ok(mut queue) = maybe_queue {
-            queue.pop_front()
+            let work = queue.pop_front();
+            self.inner.space.notify_one();
+            work
         } else {
             panic!("WorkQueue::get_work() tried to lock a poisoned mutex");
         }
     }
 
+    // Blocks until there is work to hand out
+    pub fn wait_work(&self) -> T {
+        let mut queue = self.inner.queue.lock().expect("WorkQueue::wait_work() tried to lock a poisoned mutex");
+        loop {
+            if let Some(work) = queue.pop_front() {
+                self.inner.space.notify_one();
+                return work;
+            }
+            queue = self.inner.available.wait(queue).expect("WorkQueue::wait_work() tried to lock a poisoned mutex");
+        }
+    }
+
+    // Blocks until there is work or the timeout passes, whichever comes first
+    pub fn wait_work_timeout(&self, timeout: Duration) -> Option<T> {
+        let queue = self.inner.queue.lock().expect("WorkQueue::wait_work_timeout() tried to lock a poisoned mutex");
+        let (mut queue, _) = self.inner.available
+            .wait_timeout_while(queue, timeout, |queue| queue.is_empty())
+            .expect("WorkQueue::wait_work_timeout() tried to lock a poisoned mutex");
+        let work = queue.pop_front();
+        self.inner.space.notify_one();
+        work
+    }
+
     pub fn add_work(&self, work: T) -> usize {
-        if let Ok(mut queue) = self.inner.lock() {
+        if let Ok(mut queue) = self.inner.queue.lock() {
+            if let OverflowPolicy::Coalesce(supersedes) = self.inner.policy {
+                if let Some(queued) = queue.iter_mut().find(|queued| supersedes(queued, &work)) {
+                    *queued = work;
+                    return queue.len();
+                }
+            }
+
+            if let Some(capacity) = self.inner.capacity {
+                if queue.len() >= capacity {
+                    match self.inner.policy {
+                        OverflowPolicy::DropOldest => {
+                            queue.pop_front();
+                            self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+                        },
+                        OverflowPolicy::DropNewest => {
+                            self.inner.dropped.fetch_add(1, Ordering::Relaxed);
+                            return queue.len();
+                        },
+                        OverflowPolicy::Coalesce(_) | OverflowPolicy::Block => {
+                            queue = self.inner.space
+                                .wait_while(queue, |queue| queue.len() >= capacity)
+                                .expect("WorkQueue::add_work() tried to lock a poisoned mutex");
+                        },
+                    }
+                }
+            }
+
             queue.push_back(work);
+            self.inner.available.notify_one();
 
             queue.len()
         } else {
@@ -0,0 +1,246 @@
+// Decoders for the strip_state and bus_state words in the VoiceMeeter RT packet.
+// Bit layout follows the VMRTSTATE_MODE_* definitions in the VBAN specification.
+
+pub const STATE_MUTE: u32 = 0x00000001;
+pub const STATE_SOLO: u32 = 0x00000002;
+pub const STATE_MONO: u32 = 0x00000004;
+pub const STATE_MUTE_CENTER: u32 = 0x00000008;
+
+pub const STATE_BUS_MODE_MASK: u32 = 0x000000f0;
+
+pub const STATE_EQ: u32 = 0x00000100;
+pub const STATE_CROSS: u32 = 0x00000200;
+pub const STATE_EQ_B: u32 = 0x00000800;
+
+pub const STATE_BUS_A1: u32 = 0x00001000;
+pub const STATE_BUS_A2: u32 = 0x00002000;
+pub const STATE_BUS_A3: u32 = 0x00004000;
+pub const STATE_BUS_A4: u32 = 0x00008000;
+pub const STATE_BUS_A5: u32 = 0x00080000;
+pub const STATE_BUS_B1: u32 = 0x00010000;
+pub const STATE_BUS_B2: u32 = 0x00020000;
+pub const STATE_BUS_B3: u32 = 0x00040000;
+
+pub const STATE_PAN_MASK: u32 = 0x00f00000;
+
+pub const STATE_POST_FX_REVERB: u32 = 0x01000000;
+pub const STATE_POST_FX_DELAY: u32 = 0x02000000;
+pub const STATE_POST_FX_1: u32 = 0x04000000;
+pub const STATE_POST_FX_2: u32 = 0x08000000;
+
+pub const STATE_SELECT: u32 = 0x10000000;
+pub const STATE_MONITOR: u32 = 0x20000000;
+
+const BUS_A_FLAGS: [u32; 5] = [STATE_BUS_A1, STATE_BUS_A2, STATE_BUS_A3, STATE_BUS_A4, STATE_BUS_A5];
+const BUS_B_FLAGS: [u32; 3] = [STATE_BUS_B1, STATE_BUS_B2, STATE_BUS_B3];
+
+// Generates a getter and a `with_` builder for a single bit of the state word
+macro_rules! state_flag {
+  ($get:ident, $with:ident, $flag:expr) => {
+    pub fn $get(&self) -> bool {
+      return self.bits & $flag == $flag;
+    }
+
+    pub fn $with(self, on: bool) -> Self {
+      return self.with_flag($flag, on);
+    }
+  };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PanMode {
+  Normal,
+  ColorPanel,
+  Modulation,
+  Unknown(u8)
+}
+
+impl PanMode {
+  pub fn from(val: u8) -> Self {
+    match val {
+      0x0 => PanMode::Normal,
+      0x1 => PanMode::ColorPanel,
+      0x2 => PanMode::Modulation,
+      _ => PanMode::Unknown(val),
+    }
+  }
+
+  pub fn value(&self) -> u8 {
+    match *self {
+      PanMode::Normal => 0x0,
+      PanMode::ColorPanel => 0x1,
+      PanMode::Modulation => 0x2,
+      PanMode::Unknown(val) => val & 0x0f,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BusMode {
+  Normal,
+  MixDown,
+  Repeat,
+  MixDownB,
+  Composite,
+  UpMixTv,
+  UpMix2,
+  UpMix4,
+  UpMix6,
+  Center,
+  Lfe,
+  Rear,
+  Unknown(u8)
+}
+
+impl BusMode {
+  pub fn from(val: u8) -> Self {
+    match val {
+      0x0 => BusMode::Normal,
+      0x1 => BusMode::MixDown,
+      0x2 => BusMode::Repeat,
+      0x3 => BusMode::MixDownB,
+      0x4 => BusMode::Composite,
+      0x5 => BusMode::UpMixTv,
+      0x6 => BusMode::UpMix2,
+      0x7 => BusMode::UpMix4,
+      0x8 => BusMode::UpMix6,
+      0x9 => BusMode::Center,
+      0xa => BusMode::Lfe,
+      0xb => BusMode::Rear,
+      _ => BusMode::Unknown(val),
+    }
+  }
+
+  pub fn value(&self) -> u8 {
+    match *self {
+      BusMode::Normal => 0x0,
+      BusMode::MixDown => 0x1,
+      BusMode::Repeat => 0x2,
+      BusMode::MixDownB => 0x3,
+      BusMode::Composite => 0x4,
+      BusMode::UpMixTv => 0x5,
+      BusMode::UpMix2 => 0x6,
+      BusMode::UpMix4 => 0x7,
+      BusMode::UpMix6 => 0x8,
+      BusMode::Center => 0x9,
+      BusMode::Lfe => 0xa,
+      BusMode::Rear => 0xb,
+      BusMode::Unknown(val) => val & 0x0f,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct StripState {
+  pub bits: u32
+}
+
+impl StripState {
+  pub fn from_bits(bits: u32) -> Self {
+    StripState { bits }
+  }
+
+  fn with_flag(mut self, flag: u32, on: bool) -> Self {
+    if on {
+      self.bits |= flag;
+    } else {
+      self.bits &= !flag;
+    }
+    self
+  }
+
+  state_flag!(mute, with_mute, STATE_MUTE);
+  state_flag!(solo, with_solo, STATE_SOLO);
+  state_flag!(mono, with_mono, STATE_MONO);
+  state_flag!(mute_center, with_mute_center, STATE_MUTE_CENTER);
+  state_flag!(eq, with_eq, STATE_EQ);
+  state_flag!(cross, with_cross, STATE_CROSS);
+  state_flag!(eq_b, with_eq_b, STATE_EQ_B);
+  state_flag!(a1, with_a1, STATE_BUS_A1);
+  state_flag!(a2, with_a2, STATE_BUS_A2);
+  state_flag!(a3, with_a3, STATE_BUS_A3);
+  state_flag!(a4, with_a4, STATE_BUS_A4);
+  state_flag!(a5, with_a5, STATE_BUS_A5);
+  state_flag!(b1, with_b1, STATE_BUS_B1);
+  state_flag!(b2, with_b2, STATE_BUS_B2);
+  state_flag!(b3, with_b3, STATE_BUS_B3);
+  state_flag!(post_fx_reverb, with_post_fx_reverb, STATE_POST_FX_REVERB);
+  state_flag!(post_fx_delay, with_post_fx_delay, STATE_POST_FX_DELAY);
+  state_flag!(post_fx_1, with_post_fx_1, STATE_POST_FX_1);
+  state_flag!(post_fx_2, with_post_fx_2, STATE_POST_FX_2);
+
+  // Physical bus routing, index 0 is A1
+  pub fn bus_a(&self, index: usize) -> bool {
+    match BUS_A_FLAGS.get(index) {
+      Some(flag) => self.bits & flag == *flag,
+      None => false
+    }
+  }
+
+  pub fn with_bus_a(self, index: usize, on: bool) -> Self {
+    match BUS_A_FLAGS.get(index) {
+      Some(flag) => self.with_flag(*flag, on),
+      None => self
+    }
+  }
+
+  // Virtual bus routing, index 0 is B1
+  pub fn bus_b(&self, index: usize) -> bool {
+    match BUS_B_FLAGS.get(index) {
+      Some(flag) => self.bits & flag == *flag,
+      None => false
+    }
+  }
+
+  pub fn with_bus_b(self, index: usize, on: bool) -> Self {
+    match BUS_B_FLAGS.get(index) {
+      Some(flag) => self.with_flag(*flag, on),
+      None => self
+    }
+  }
+
+  pub fn pan_mode(&self) -> PanMode {
+    PanMode::from(((self.bits & STATE_PAN_MASK) >> 20) as u8)
+  }
+
+  pub fn with_pan_mode(mut self, mode: PanMode) -> Self {
+    self.bits = (self.bits & !STATE_PAN_MASK) | ((mode.value() as u32) << 20);
+    self
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct BusState {
+  pub bits: u32
+}
+
+impl BusState {
+  pub fn from_bits(bits: u32) -> Self {
+    BusState { bits }
+  }
+
+  fn with_flag(mut self, flag: u32, on: bool) -> Self {
+    if on {
+      self.bits |= flag;
+    } else {
+      self.bits &= !flag;
+    }
+    self
+  }
+
+  state_flag!(mute, with_mute, STATE_MUTE);
+  state_flag!(mono, with_mono, STATE_MONO);
+  state_flag!(eq, with_eq, STATE_EQ);
+  state_flag!(eq_b, with_eq_b, STATE_EQ_B);
+  state_flag!(selected, with_selected, STATE_SELECT);
+  state_flag!(monitor, with_monitor, STATE_MONITOR);
+
+  pub fn mode(&self) -> BusMode {
+    BusMode::from(((self.bits & STATE_BUS_MODE_MASK) >> 4) as u8)
+  }
+
+  pub fn with_mode(mut self, mode: BusMode) -> Self {
+    self.bits = (self.bits & !STATE_BUS_MODE_MASK) | ((mode.value() as u32) << 4);
+    self
+  }
+}
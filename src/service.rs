@@ -0,0 +1,445 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time;
+use std::time::SystemTime;
+
+use packed_struct::prelude::*;
+
+use bridge::{Bridge, BridgeEvent, BridgeOutput, BridgeStatus};
+use capture::{Capture, Link};
+use config::Config;
+use discovery::{bridge_identity, ping_reply, ping_request, Discovery, VbanHost};
+use queue::{OverflowPolicy, WorkQueue};
+use transport::Transport;
+use vban::{stream_name, PingPacket, RTPacket, RegisterRT, VBANHeader, VBANPing0, VBANProtocol, VBANServiceHeader, VbanError, SERVICE_RT_REGISTER};
+use xctrl::{XctrlError, XctrlMessage};
+
+// Runs a Bridge between two transports: one shared by every X-Touch surface, one for VoiceMeeter
+
+// Most work items any one queue holds before its overflow policy kicks in
+const QUEUE_CAPACITY: usize = 256;
+
+// How often the config file is checked for changes
+const CONFIG_POLL_MS: u64 = 500;
+
+// How often the bridge works out what the surfaces should show
+pub const REFRESH_MS: u64 = 50;
+
+// How often each surface is sent everything it shows, to cover anything lost on the way
+pub const FULL_REFRESH_MS: u64 = 2000;
+
+// How often pings go out looking for VoiceMeeter, when it's being found rather than given
+const DISCOVERY_PING_MS: u64 = 10000;
+
+// A datagram for VoiceMeeter, wherever it is at the time, or for the address given
+type VbanOutgoing = (Option<String>, Vec<u8>);
+
+// Datagrams on one link that couldn't be read. Every one is counted, but they're logged at most
+// once a second so a stream of them can't flood the log.
+struct BadPackets {
+    link: &'static str,
+    count: usize,
+    last_logged: Option<SystemTime>
+}
+
+impl BadPackets {
+    fn new(link: &'static str) -> Self {
+        BadPackets { link, count: 0, last_logged: None }
+    }
+
+    fn record(&mut self, error: &dyn fmt::Display) {
+        self.count += 1;
+        let due = match self.last_logged {
+            Some(last_logged) => SystemTime::now().duration_since(last_logged).map(|since| since.as_secs() >= 1).unwrap_or(true),
+            None => true
+        };
+        if due {
+            println!("Ignoring a bad {} packet, {} so far: {}", self.link, self.count, error);
+            self.last_logged = Some(SystemTime::now());
+        }
+    }
+}
+
+fn xctrl_incoming_thread<T: Transport + 'static>(queue: WorkQueue<(SocketAddr, Vec<u8>)>, transport: Arc<T>, capture: Option<Capture>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        loop {
+            let mut buf = [0; 512];
+            match transport.recv_from(&mut buf) {
+                Ok((amt, src)) => {
+                    if let Some(capture) = capture.as_ref() {
+                        capture.record(Link::XctrlIn, &src.to_string(), &buf[..amt]);
+                    }
+                    queue.add_work((src, buf[..amt].to_vec()));
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return,
+                Err(e) => {
+                    eprintln!("Error receiving XCtrl data: {:?}", e);
+                }
+            }
+        }
+    })
+}
+
+fn vban_incoming_thread<T: Transport + 'static>(queue: WorkQueue<(SocketAddr, Vec<u8>)>, transport: Arc<T>, capture: Option<Capture>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        loop {
+            let mut buf = [0; 1412];
+            match transport.recv_from(&mut buf) {
+                Ok((amt, src)) => {
+                    if let Some(capture) = capture.as_ref() {
+                        capture.record(Link::VbanIn, &src.to_string(), &buf[..amt]);
+                    }
+                    queue.add_work((src, buf[..amt].to_vec()));
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return,
+                Err(e) => {
+                    eprintln!("Error receiving VBAN data: {:?}", e);
+                }
+            }
+        }
+    })
+}
+
+fn xctrl_outgoing_thread<T: Transport + 'static>(queue: WorkQueue<(String, Vec<u8>)>, transport: Arc<T>, capture: Option<Capture>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        loop {
+            let (ip, message) = queue.wait_work();
+            if let Some(capture) = capture.as_ref() {
+                capture.record(Link::XctrlOut, &ip, &message);
+            }
+            match transport.send_to(&message, &ip) {
+                Ok(_) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return,
+                Err(e) => eprintln!("Error sending XCtrl data: {:?}", e)
+            }
+        }
+    })
+}
+
+fn vban_outgoing_thread<T: Transport + 'static>(voicemeeter: Arc<Mutex<String>>, queue: WorkQueue<VbanOutgoing>, transport: Arc<T>, capture: Option<Capture>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        loop {
+            let (address, message) = queue.wait_work();
+            let ip = match address {
+                Some(address) => address,
+                None => voicemeeter.lock().expect("vban_outgoing_thread() tried to lock a poisoned mutex").clone()
+            };
+            if let Some(capture) = capture.as_ref() {
+                capture.record(Link::VbanOut, &ip, &message);
+            }
+            match transport.send_to(&message, &ip) {
+                Ok(_) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return,
+                Err(e) => eprintln!("Error sending VBAN data: {:?}", e)
+            }
+        }
+    })
+}
+
+fn xctrl_processor_thread(devices: Vec<String>, incoming: WorkQueue<(SocketAddr, Vec<u8>)>, state: WorkQueue<BridgeEvent>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut bad_packets = BadPackets::new("XCtrl");
+        loop {
+            let (src, buf) = incoming.wait_work();
+
+            let device = match device_index(&devices, &src) {
+                Some(device) => device,
+                None => {
+                    println!("Ignoring XCtrl data from unknown device {}", src);
+                    continue;
+                }
+            };
+
+            match decode_xctrl(device, &buf) {
+                Ok(events) => {
+                    for event in events {
+                        state.add_work(event);
+                    }
+                },
+                Err(e) => bad_packets.record(&e)
+            }
+        }
+    })
+}
+
+// Devices are told apart by IP, they all talk to the same port
+pub fn device_index(devices: &[String], src: &SocketAddr) -> Option<usize> {
+    devices.iter().position(|address| match address.parse::<SocketAddr>() {
+        Ok(address) => address.ip() == src.ip(),
+        Err(_) => false
+    })
+}
+
+// Everything in a datagram from a surface the bridge has a use for. Well formed messages it
+// doesn't know are quietly left out, they're not worth a line in the log.
+pub fn decode_xctrl(device: usize, buf: &[u8]) -> Result<Vec<BridgeEvent>, XctrlError> {
+    Ok(XctrlMessage::try_parse(buf)?
+        .into_iter()
+        .filter(|message| !matches!(message, XctrlMessage::Unknown(_)))
+        .map(|message| BridgeEvent::Xctrl(device, message))
+        .collect())
+}
+
+fn register_packet(stream: [u8; 16]) -> Vec<u8> {
+    let packet: RegisterRT = RegisterRT {
+        header: VBANServiceHeader {
+            header: VBANHeader {
+                vban: [0x56, 0x42, 0x41, 0x4e], // "VBAN"
+                protocol: VBANProtocol::Service as u8
+            },
+            function: 0,
+            service: SERVICE_RT_REGISTER,
+            additional_info: 50,
+            stream_name: stream,
+            frame_id: 1
+        },
+        packet_ids: [1; 128]
+    };
+    packet.pack().expect("couldn't pack the packet").to_vec()
+}
+
+fn vban_heartbeat_thread(stream: [u8; 16], vban_outgoing: WorkQueue<VbanOutgoing>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let packet_data = register_packet(stream);
+
+        loop {
+            vban_outgoing.add_work((None, packet_data.clone()));
+            thread::sleep(time::Duration::from_millis(10000));
+        }
+    })
+}
+
+fn vban_discovery_thread(broadcast: String, vban_outgoing: WorkQueue<VbanOutgoing>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let identity = bridge_identity();
+        let mut frame_id: u32 = 0;
+
+        loop {
+            frame_id = frame_id.wrapping_add(1);
+            vban_outgoing.add_work((Some(broadcast.clone()), ping_request(&identity, frame_id)));
+            thread::sleep(time::Duration::from_millis(DISCOVERY_PING_MS));
+        }
+    })
+}
+
+// Picks the RT packets out of what VoiceMeeter sends, anything else it sends is let go
+pub fn decode_vban(message: &[u8], rt_stream: &[u8; 16]) -> Result<Option<BridgeEvent>, VbanError> {
+    let header = VBANHeader::parse(message)?;
+    if header.protocol != VBANProtocol::Service as u8 {
+        return Ok(None);
+    }
+    let service_header = VBANServiceHeader::parse(message)?;
+    if service_header.service == SERVICE_RT_REGISTER && service_header.additional_info == 1 {
+        println!("VoiceMeeter registered");
+    } else if service_header.stream_name == *rt_stream {
+        return Ok(Some(BridgeEvent::Vban(Box::new(RTPacket::parse(message)?))));
+    }
+    Ok(None)
+}
+
+// Where VoiceMeeter is and how it was found, shared by the VBAN threads
+struct VoiceMeeterPeer {
+    address: Arc<Mutex<String>>,
+    discovery: Option<Discovery>,
+    register_stream: [u8; 16],
+    // What the bridge answers pings with
+    identity: VBANPing0
+}
+
+impl VoiceMeeterPeer {
+    // Pings are answered straight back to whoever sent them. An answer from the VoiceMeeter being
+    // looked for, from an address other than the one in use, moves VBAN over to that address.
+    fn handle_ping(&mut self, src: SocketAddr, ping: &PingPacket, frame_id: u32, vban_outgoing: &WorkQueue<VbanOutgoing>) {
+        if !ping.is_reply() {
+            vban_outgoing.add_work((Some(src.to_string()), ping_reply(&self.identity, frame_id)));
+            return;
+        }
+        let discovery = match self.discovery.as_mut() {
+            Some(discovery) => discovery,
+            None => return
+        };
+        let host = VbanHost::from_ping(src, ping);
+        let mut address = self.address.lock().expect("VoiceMeeterPeer::handle_ping() tried to lock a poisoned mutex");
+        if discovery.selector.matches(&host) && *address != src.to_string() {
+            println!("Using VoiceMeeter on {}", host);
+            *address = src.to_string();
+            discovery.selector = discovery.selector.pinned(&host);
+            vban_outgoing.add_work((None, register_packet(self.register_stream)));
+        }
+    }
+}
+
+fn vban_processor_thread(rt_stream: [u8; 16], mut peer: VoiceMeeterPeer, vban_incoming: WorkQueue<(SocketAddr, Vec<u8>)>, vban_outgoing: WorkQueue<VbanOutgoing>, state: WorkQueue<BridgeEvent>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut bad_packets = BadPackets::new("VBAN");
+        let mut ping_frame_id: u32 = 0;
+        loop {
+            let (src, message) = vban_incoming.wait_work();
+            // RT packets come first, anything else can be checked for pings at leisure
+            match decode_vban(&message, &rt_stream) {
+                Ok(Some(event)) => {
+                    state.add_work(event);
+                },
+                Ok(None) if PingPacket::is_ping(&message) => {
+                    match PingPacket::parse(&message) {
+                        Ok(ping) => {
+                            ping_frame_id = ping_frame_id.wrapping_add(1);
+                            peer.handle_ping(src, &ping, ping_frame_id, &vban_outgoing);
+                        },
+                        Err(e) => bad_packets.record(&e)
+                    }
+                },
+                Ok(None) => {},
+                Err(e) => bad_packets.record(&e)
+            }
+        }
+    })
+}
+
+// Hands the bridge a fresh copy of the config whenever the file changes. One that doesn't load is
+// reported and left for the next save, the bridge keeps running on the last good one.
+fn config_watch_thread(path: PathBuf, state: WorkQueue<BridgeEvent>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let modified = |path: &PathBuf| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let mut last_modified = modified(&path);
+
+        loop {
+            thread::sleep(time::Duration::from_millis(CONFIG_POLL_MS));
+            let now_modified = modified(&path);
+            if now_modified.is_none() || now_modified == last_modified {
+                continue;
+            }
+            last_modified = now_modified;
+
+            match Config::load(&path) {
+                Ok(config) => {
+                    state.add_work(BridgeEvent::Reload(Box::new(config)));
+                },
+                Err(e) => println!("Keeping the last good config, {}", e)
+            }
+        }
+    })
+}
+
+fn bridge_thread(mut bridge: Bridge, state: WorkQueue<BridgeEvent>, xctrl_incoming: WorkQueue<(SocketAddr, Vec<u8>)>, xctrl_outgoing: WorkQueue<(String, Vec<u8>)>, vban_incoming: WorkQueue<(SocketAddr, Vec<u8>)>, vban_outgoing: WorkQueue<VbanOutgoing>, status: Option<WorkQueue<BridgeStatus>>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_update_send = SystemTime::now();
+        let mut last_drop_report = SystemTime::now();
+        let mut dropped = [0; 5];
+
+        loop {
+            // Wake up at least as often as the surface is refreshed
+            let mut outgoing = match state.wait_work_timeout(time::Duration::from_millis(REFRESH_MS)) {
+                Some(event) => bridge.handle(event),
+                None => Vec::new()
+            };
+
+            if SystemTime::now().duration_since(last_update_send).expect("Time went backwards").as_millis() > REFRESH_MS as u128 {
+                last_update_send = SystemTime::now();
+                outgoing.extend(bridge.refresh());
+
+                if SystemTime::now().duration_since(last_drop_report).expect("Time went backwards").as_secs() >= 10 {
+                    let now_dropped = [xctrl_incoming.dropped(), xctrl_outgoing.dropped(), vban_incoming.dropped(), vban_outgoing.dropped(), state.dropped()];
+                    if now_dropped != dropped {
+                        println!("Queues falling behind, dropped so far: xctrl in {}, xctrl out {}, vban in {}, vban out {}, state {}", now_dropped[0], now_dropped[1], now_dropped[2], now_dropped[3], now_dropped[4]);
+                        dropped = now_dropped;
+                    }
+                    last_drop_report = SystemTime::now();
+                }
+            }
+
+            for output in outgoing {
+                match output {
+                    BridgeOutput::Xctrl(address, data) => {
+                        xctrl_outgoing.add_work((address, data));
+                    },
+                    BridgeOutput::Vban(data) => {
+                        vban_outgoing.add_work((None, data));
+                    },
+                    BridgeOutput::Status(report) => {
+                        if let Some(status) = status.as_ref() {
+                            status.add_work(report);
+                        }
+                    }
+                };
+            }
+        }
+    })
+}
+
+// What runs alongside the bridge, on top of its own threads
+#[derive(Default)]
+pub struct StartOptions {
+    // Applies any changes saved to this config file while running
+    pub config_path: Option<PathBuf>,
+    // Records every datagram on both links, in and out
+    pub capture: Option<Capture>,
+    // Keeps looking for VoiceMeeter with pings and follows it if its address changes
+    pub discovery: Option<Discovery>,
+    // Where the bridge's status reports go for the caller to show, without one they're dropped
+    pub status: Option<WorkQueue<BridgeStatus>>
+}
+
+// Starts every thread the bridge needs and hands back their handles. None of them finish while
+// the transports are open.
+pub fn start<X: Transport + 'static, V: Transport + 'static>(bridge: Bridge, xctrl: X, vban: V, vban_address: String) -> Vec<thread::JoinHandle<()>> {
+    start_with(bridge, xctrl, vban, vban_address, StartOptions::default())
+}
+
+// As start, and also applies any changes saved to the config file while running
+pub fn start_watching<X: Transport + 'static, V: Transport + 'static>(bridge: Bridge, xctrl: X, vban: V, vban_address: String, config_path: PathBuf) -> Vec<thread::JoinHandle<()>> {
+    start_with(bridge, xctrl, vban, vban_address, StartOptions { config_path: Some(config_path), ..StartOptions::default() })
+}
+
+pub fn start_with<X: Transport + 'static, V: Transport + 'static>(bridge: Bridge, xctrl: X, vban: V, vban_address: String, options: StartOptions) -> Vec<thread::JoinHandle<()>> {
+    let xctrl = Arc::new(xctrl);
+    let vban = Arc::new(vban);
+    let devices: Vec<String> = bridge.devices.iter().map(|device| device.address.clone()).collect();
+    let rt_stream = stream_name(&bridge.streams.rt);
+    let register_stream = stream_name(&bridge.streams.register);
+
+    // Surface input is never thrown away, the receive thread waits and the socket buffers instead.
+    // Surface output can be lost as the periodic full refresh puts it right again.
+    let xctrl_incoming: WorkQueue<(SocketAddr, Vec<u8>)> = WorkQueue::bounded(QUEUE_CAPACITY, OverflowPolicy::Block);
+    let xctrl_outgoing: WorkQueue<(String, Vec<u8>)> = WorkQueue::bounded(QUEUE_CAPACITY, OverflowPolicy::DropOldest);
+    // Only the newest RT packet matters, so one arriving replaces any still waiting and surface
+    // events never queue up behind stale state. With the queue full of surface events the
+    // processors wait for the bridge instead of dropping anything.
+    let state: WorkQueue<BridgeEvent> = WorkQueue::bounded(QUEUE_CAPACITY, OverflowPolicy::Coalesce(BridgeEvent::supersedes));
+
+    let vban_incoming: WorkQueue<(SocketAddr, Vec<u8>)> = WorkQueue::bounded(QUEUE_CAPACITY, OverflowPolicy::DropOldest);
+    let vban_outgoing: WorkQueue<VbanOutgoing> = WorkQueue::bounded(QUEUE_CAPACITY, OverflowPolicy::DropOldest);
+    let voicemeeter = Arc::new(Mutex::new(vban_address));
+
+    let mut threads = Vec::new();
+
+    println!("Starting tx/rx threads for XCtrl");
+    threads.push(xctrl_incoming_thread(xctrl_incoming.clone(), xctrl.clone(), options.capture.clone()));
+    threads.push(xctrl_outgoing_thread(xctrl_outgoing.clone(), xctrl, options.capture.clone()));
+
+    println!("Starting tx/rx threads for VBAN");
+    threads.push(vban_incoming_thread(vban_incoming.clone(), vban.clone(), options.capture.clone()));
+    threads.push(vban_outgoing_thread(voicemeeter.clone(), vban_outgoing.clone(), vban, options.capture.clone()));
+
+    println!("Starting processor threads");
+    threads.push(xctrl_processor_thread(devices, xctrl_incoming.clone(), state.clone()));
+    if let Some(discovery) = options.discovery.as_ref() {
+        println!("Following VoiceMeeter with pings to {}", discovery.broadcast);
+        threads.push(vban_discovery_thread(discovery.broadcast.clone(), vban_outgoing.clone()));
+    }
+    let peer = VoiceMeeterPeer { address: voicemeeter, discovery: options.discovery, register_stream, identity: bridge_identity() };
+    threads.push(vban_processor_thread(rt_stream, peer, vban_incoming.clone(), vban_outgoing.clone(), state.clone()));
+    threads.push(vban_heartbeat_thread(register_stream, vban_outgoing.clone()));
+    if let Some(path) = options.config_path {
+        println!("Watching {} for changes", path.display());
+        threads.push(config_watch_thread(path, state.clone()));
+    }
+    threads.push(bridge_thread(bridge, state, xctrl_incoming, xctrl_outgoing, vban_incoming, vban_outgoing, options.status));
+
+    threads
+}
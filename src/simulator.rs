@@ -0,0 +1,433 @@
+// A stand-in for VoiceMeeter that speaks enough of the VBAN RT service to run the bridge against
+// without a Windows machine. It keeps the state VoiceMeeter reports in RT packets and changes it
+// for the TEXT and MIDI commands the bridge sends, the way VoiceMeeter would.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use packed_struct::prelude::*;
+use toml;
+
+use bridge::{MASTER_FADER_CONTROL, MASTER_FADER_STATUS};
+use config::ConfigError;
+use fader_law::FaderRange;
+use rt_state::{BusState, StripState};
+use transport::Transport;
+use vban::*;
+use xctrl::{XctrlButtonType, XctrlMessage};
+
+// Low enough that the meters show nothing
+pub const SILENT: f32 = -200.0;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulatedChannel {
+  pub label: String,
+  pub gain: f32,
+  // Strips only, the level of the strip into each bus
+  pub gain_layers: [f32; 8],
+  pub mute: bool,
+  // Strips only
+  pub solo: bool,
+  pub mono: bool,
+  // What the meters read, in dB
+  pub level: f32
+}
+
+impl Default for SimulatedChannel {
+  fn default() -> Self {
+    SimulatedChannel {
+      label: String::new(),
+      gain: 0.0,
+      gain_layers: [0.0; 8],
+      mute: false,
+      solo: false,
+      mono: false,
+      level: SILENT
+    }
+  }
+}
+
+// A script to run once the simulator has been up for a while
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimStep {
+  pub at_ms: u64,
+  pub script: String
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimScenario {
+  pub edition: String,
+  // What the simulator answers VBAN pings with
+  pub host_name: String,
+  // How often subscribers get an RT packet
+  pub interval_ms: u64,
+  pub strips: Vec<SimulatedChannel>,
+  pub buses: Vec<SimulatedChannel>,
+  pub steps: Vec<SimStep>
+}
+
+impl Default for SimScenario {
+  fn default() -> Self {
+    SimScenario {
+      edition: "potato".to_string(),
+      host_name: "voicemeeter-sim".to_string(),
+      interval_ms: 20,
+      strips: Vec::new(),
+      buses: Vec::new(),
+      steps: Vec::new()
+    }
+  }
+}
+
+impl SimScenario {
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<SimScenario, ConfigError> {
+    let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+    SimScenario::parse(&text)
+  }
+
+  pub fn parse(text: &str) -> Result<SimScenario, ConfigError> {
+    let scenario: SimScenario = toml::from_str(text).map_err(ConfigError::Parse)?;
+    let edition = scenario.edition()?;
+    if scenario.strips.len() > edition.strips() || scenario.buses.len() > edition.buses() {
+      return Err(ConfigError::Invalid(format!(
+        "{} has {} strips and {} buses", scenario.edition, edition.strips(), edition.buses()
+      )));
+    }
+    if scenario.interval_ms == 0 {
+      return Err(ConfigError::Invalid("interval_ms must be more than 0".to_string()));
+    }
+    Ok(scenario)
+  }
+
+  pub fn edition(&self) -> Result<VoiceMeeterType, ConfigError> {
+    VoiceMeeterType::from_name(&self.edition)
+      .ok_or_else(|| ConfigError::Invalid("edition must be 'standard', 'banana' or 'potato'".to_string()))
+  }
+
+  pub fn interval(&self) -> Duration {
+    Duration::from_millis(self.interval_ms)
+  }
+}
+
+// What a packet sent to the simulator asked for
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimRequest {
+  // Send RT packets to the sender for this long
+  Register(Duration),
+  // Commands that were applied
+  Applied(usize),
+  // A ping, to be answered with ping_reply()
+  Ping,
+  Ignored
+}
+
+pub struct VoiceMeeterSim {
+  pub edition: VoiceMeeterType,
+  pub host_name: String,
+  pub strips: Vec<SimulatedChannel>,
+  pub buses: Vec<SimulatedChannel>,
+  // The curve MIDI fader levels are read with, which VoiceMeeter's MIDI mapping would match
+  pub fader_range: FaderRange,
+  frame_id: u32
+}
+
+impl VoiceMeeterSim {
+  pub fn new(edition: VoiceMeeterType) -> Self {
+    VoiceMeeterSim {
+      edition,
+      host_name: "voicemeeter-sim".to_string(),
+      strips: vec![SimulatedChannel::default(); edition.strips()],
+      buses: vec![SimulatedChannel::default(); edition.buses()],
+      fader_range: FaderRange::default(),
+      frame_id: 0
+    }
+  }
+
+  pub fn from_scenario(scenario: &SimScenario) -> Result<Self, ConfigError> {
+    let mut sim = VoiceMeeterSim::new(scenario.edition()?);
+    sim.host_name = scenario.host_name.clone();
+    for (i, strip) in scenario.strips.iter().enumerate() {
+      sim.strips[i] = strip.clone();
+    }
+    for (i, bus) in scenario.buses.iter().enumerate() {
+      sim.buses[i] = bus.clone();
+    }
+    Ok(sim)
+  }
+
+  // The next RT packet, as VoiceMeeter would send it to a subscriber
+  pub fn rt_packet(&mut self) -> RTPacket {
+    self.frame_id = self.frame_id.wrapping_add(1);
+    let mut packet = RTPacket::new(self.edition, "Voicemeeter-RTP", self.frame_id);
+    for (i, strip) in self.strips.iter().enumerate() {
+      packet.set_input_gain(i, strip.gain);
+      for layer in 1..8 {
+        packet.set_input_gain_layer(layer, i, strip.gain_layers[layer]);
+      }
+      packet.set_strip_label(i, &strip.label);
+      packet.set_input_level(i, strip.level);
+      packet.set_strip_state(i, StripState::default().with_mute(strip.mute).with_solo(strip.solo).with_mono(strip.mono));
+    }
+    for (i, bus) in self.buses.iter().enumerate() {
+      packet.set_output_gain(i, bus.gain);
+      packet.set_bus_label(i, &bus.label);
+      packet.set_output_level(i, bus.level);
+      packet.set_bus_state(i, BusState::default().with_mute(bus.mute).with_mono(bus.mono));
+    }
+    packet
+  }
+
+  fn channel(&mut self, channel: VoiceMeeterChannel) -> Option<&mut SimulatedChannel> {
+    match channel {
+      VoiceMeeterChannel::Strip(id) => self.strips.get_mut(id),
+      VoiceMeeterChannel::Bus(id) => self.buses.get_mut(id),
+    }
+  }
+
+  // Pan, EQ and sends aren't in the RT packet, so they're accepted and forgotten
+  pub fn apply_command(&mut self, command: &VoiceMeeterCommand) -> bool {
+    let is_strip = matches!(command.channel, VoiceMeeterChannel::Strip(_));
+    let gain_layers = self.edition.gain_layers();
+    let channel = match self.channel(command.channel) {
+      Some(channel) => channel,
+      None => return false
+    };
+    match command.parameter {
+      VoiceMeeterParameter::Gain(gain) => {
+        channel.gain = gain.clamp(-60.0, 12.0);
+        channel.gain_layers[0] = channel.gain;
+      },
+      VoiceMeeterParameter::GainLayer(layer, gain) if is_strip && layer < gain_layers => {
+        channel.gain_layers[layer] = gain.clamp(-60.0, 12.0);
+        if layer == 0 {
+          channel.gain = channel.gain_layers[0];
+        }
+      },
+      VoiceMeeterParameter::GainLayer(..) => return false,
+      VoiceMeeterParameter::Mute(on) => channel.mute = on,
+      // Buses have no solo
+      VoiceMeeterParameter::Solo(on) if is_strip => channel.solo = on,
+      VoiceMeeterParameter::Solo(_) => return false,
+      VoiceMeeterParameter::Mono(on) => channel.mono = on,
+      _ => ()
+    };
+    true
+  }
+
+  // Runs a VoiceMeeter script of statements split by ';' or new lines, returning how many were
+  // applied. On top of the statements VoiceMeeterCommand reads, `Strip[n].Label = "text"` and
+  // `Bus[n].Label` rename a channel, and `.Level` sets what its meters read.
+  pub fn apply_script(&mut self, script: &str) -> usize {
+    let mut applied = 0;
+    for statement in script.split(&[';', '\n'][..]).map(|s| s.trim()).filter(|s| !s.is_empty()) {
+      let ok = match VoiceMeeterCommand::parse(statement) {
+        Some(command) => self.apply_command(&command),
+        None => self.apply_extra(statement)
+      };
+      if ok {
+        applied += 1;
+      } else {
+        println!("Simulator ignored '{}'", statement);
+      }
+    }
+    applied
+  }
+
+  fn apply_extra(&mut self, statement: &str) -> bool {
+    let mut sides = statement.splitn(2, '=');
+    let target = sides.next().unwrap_or("").trim();
+    let value = match sides.next() {
+      Some(value) => value.trim(),
+      None => return false
+    };
+    let dot = match target.find('.') {
+      Some(dot) => dot,
+      None => return false
+    };
+    let channel = if let Some(id) = script_index(&target[..dot], "strip") {
+      VoiceMeeterChannel::Strip(id)
+    } else if let Some(id) = script_index(&target[..dot], "bus") {
+      VoiceMeeterChannel::Bus(id)
+    } else {
+      return false;
+    };
+    let parameter = target[(dot + 1)..].trim().to_ascii_lowercase();
+    let channel = match self.channel(channel) {
+      Some(channel) => channel,
+      None => return false
+    };
+    match parameter.as_str() {
+      "label" => channel.label = value.trim_matches('"').to_string(),
+      "level" => match value.parse() {
+        Ok(level) => channel.level = level,
+        Err(_) => return false
+      },
+      _ => return false
+    };
+    true
+  }
+
+  // Applies a raw X-Touch message forwarded by the bridge in MIDI mode, read as VoiceMeeter would
+  // with a MIDI mapping that follows the surface: channel 1 for the strips and channel 9 for the
+  // buses, faders to gain and the Rec, Solo and Mute rows toggling mono, solo and mute on press.
+  // The master fader's control is read as the first bus, where the bridge puts it by default.
+  pub fn apply_midi(&mut self, message: &[u8]) -> bool {
+    if let [MASTER_FADER_STATUS, MASTER_FADER_CONTROL, value] = *message {
+      let gain = self.fader_range.gain((value as u16) << 8);
+      return self.apply_command(&VoiceMeeterCommand::new(VoiceMeeterChannel::Bus(0), VoiceMeeterParameter::Gain(gain)));
+    }
+    let bank = match message.first() {
+      Some(status) => (status & 0x0f) as usize,
+      None => return false
+    };
+    let channel = |id: usize| if bank < 8 { VoiceMeeterChannel::Strip(id) } else { VoiceMeeterChannel::Bus(id) };
+    let parameter = match XctrlMessage::decode(message) {
+      XctrlMessage::Fader { id, level } => {
+        let gain = self.fader_range.gain(level);
+        return self.apply_command(&VoiceMeeterCommand::new(channel((id % 8) as usize), VoiceMeeterParameter::Gain(gain)));
+      },
+      XctrlMessage::Button { id, pressed: true } if bank % 8 == 0 && id < XctrlButtonType::Select as u8 => id,
+      _ => return false
+    };
+    let (row, id) = (parameter & 0x18, (parameter & 0x07) as usize);
+    let current = match self.channel(channel(id)) {
+      Some(current) => current.clone(),
+      None => return false
+    };
+    let parameter = if row == XctrlButtonType::Rec as u8 {
+      VoiceMeeterParameter::Mono(!current.mono)
+    } else if row == XctrlButtonType::Solo as u8 {
+      VoiceMeeterParameter::Solo(!current.solo)
+    } else {
+      VoiceMeeterParameter::Mute(!current.mute)
+    };
+    self.apply_command(&VoiceMeeterCommand::new(channel(id), parameter))
+  }
+
+  // How VoiceMeeter answers a ping
+  pub fn ping_reply(&mut self) -> Vec<u8> {
+    self.frame_id = self.frame_id.wrapping_add(1);
+    let application = match self.edition {
+      VoiceMeeterType::Standard => "Voicemeeter",
+      VoiceMeeterType::Banana => "Voicemeeter Banana",
+      VoiceMeeterType::Potato => "Voicemeeter Potato"
+    };
+    let identity = VBANPing0::new(PING_TYPE_VIRTUAL_MIXER | PING_TYPE_RECEPTOR | PING_TYPE_TRANSMITTER, PING_FEATURE_MIDI | PING_FEATURE_TXT, [self.edition as u8, 0, 0, 0])
+      .with_names(application, "VB-Audio Software", application, &self.host_name);
+    PingPacket::new(identity, true, self.frame_id).pack().expect("couldn't pack the ping").to_vec()
+  }
+
+  // Handles a datagram sent to VoiceMeeter: a VBAN-TEXT script, VBAN MIDI, an RT registration or
+  // a ping
+  pub fn handle_packet(&mut self, buf: &[u8]) -> Result<SimRequest, VbanError> {
+    let header = VBANHeader::parse(buf)?;
+    let body = buf.get(28..).unwrap_or(&[]);
+    match VBANProtocol::from_primitive(header.protocol & 0xe0) {
+      Some(VBANProtocol::Txt) => {
+        Ok(SimRequest::Applied(self.apply_script(&String::from_utf8_lossy(body))))
+      },
+      Some(VBANProtocol::Serial) => {
+        let applied = body.chunks(3).filter(|message| message.len() == 3 && self.apply_midi(message)).count();
+        Ok(SimRequest::Applied(applied))
+      },
+      Some(VBANProtocol::Service) => {
+        let registration = VBANServiceHeader::parse(buf)?;
+        if registration.service == SERVICE_IDENTIFICATION && registration.function & SERVICE_REPLY == 0 {
+          return Ok(SimRequest::Ping);
+        }
+        if registration.service != SERVICE_RT_REGISTER {
+          return Ok(SimRequest::Ignored);
+        }
+        Ok(SimRequest::Register(Duration::from_secs(registration.additional_info as u64)))
+      },
+      _ => Ok(SimRequest::Ignored),
+    }
+  }
+}
+
+// Runs a simulator over a transport: one thread answering what's sent to it, one sending RT
+// packets to every current subscriber each interval and running the steps as they fall due. The
+// simulator is handed back so its state can be looked at or changed while it runs.
+pub fn start_simulator<T: Transport + 'static>(sim: VoiceMeeterSim, transport: T, interval: Duration, steps: Vec<SimStep>) -> (Arc<Mutex<VoiceMeeterSim>>, Vec<thread::JoinHandle<()>>) {
+  let sim = Arc::new(Mutex::new(sim));
+  let transport = Arc::new(transport);
+  let subscribers: Arc<Mutex<HashMap<SocketAddr, Instant>>> = Arc::new(Mutex::new(HashMap::new()));
+
+  let receiver = {
+    let (sim, transport, subscribers) = (sim.clone(), transport.clone(), subscribers.clone());
+    thread::spawn(move || {
+      loop {
+        let mut buf = [0; 2048];
+        let (amt, src) = match transport.recv_from(&mut buf) {
+          Ok(received) => received,
+          Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return,
+          Err(e) => {
+            eprintln!("Error receiving VBAN data: {:?}", e);
+            continue;
+          }
+        };
+        let request = sim.lock().expect("simulator receive thread found a poisoned mutex").handle_packet(&buf[..amt]);
+        match request {
+          Ok(SimRequest::Register(timeout)) => {
+            if subscribers.lock().expect("simulator receive thread found a poisoned mutex").insert(src, Instant::now() + timeout).is_none() {
+              println!("{} subscribed to RT packets", src);
+            }
+          },
+          Ok(SimRequest::Ping) => {
+            let reply = sim.lock().expect("simulator receive thread found a poisoned mutex").ping_reply();
+            match transport.send_to(&reply, &src.to_string()) {
+              Ok(_) => (),
+              Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return,
+              Err(e) => eprintln!("Error sending VBAN data: {:?}", e)
+            };
+          },
+          Ok(_) => (),
+          Err(e) => println!("Ignoring a bad packet from {}: {}", src, e)
+        };
+      }
+    })
+  };
+
+  let sender = {
+    let sim = sim.clone();
+    thread::spawn(move || {
+      let started = Instant::now();
+      let mut steps = steps;
+      steps.sort_by_key(|step| step.at_ms);
+      let mut steps = steps.into_iter().peekable();
+      loop {
+        thread::sleep(interval);
+        let mut sim = sim.lock().expect("simulator send thread found a poisoned mutex");
+        while steps.peek().map(|step| started.elapsed() >= Duration::from_millis(step.at_ms)).unwrap_or(false) {
+          let step = steps.next().unwrap();
+          println!("Running the step at {}ms", step.at_ms);
+          sim.apply_script(&step.script);
+        }
+        let packet = sim.rt_packet().pack().expect("couldn't pack the packet");
+        drop(sim);
+
+        let mut subscribers = subscribers.lock().expect("simulator send thread found a poisoned mutex");
+        let now = Instant::now();
+        subscribers.retain(|_, until| *until > now);
+        for address in subscribers.keys() {
+          match transport.send_to(&packet, &address.to_string()) {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => return,
+            Err(e) => eprintln!("Error sending VBAN data: {:?}", e)
+          };
+        }
+      }
+    })
+  };
+
+  (sim, vec![receiver, sender])
+}
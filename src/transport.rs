@@ -0,0 +1,79 @@
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Something datagrams can be sent over and received from, so the I/O threads don't need a
+// real socket behind them. Returning BrokenPipe means the other end is gone for good.
+pub trait Transport: Send + Sync {
+  fn send_to(&self, buf: &[u8], address: &str) -> io::Result<usize>;
+  fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+}
+
+impl Transport for UdpSocket {
+  fn send_to(&self, buf: &[u8], address: &str) -> io::Result<usize> {
+    UdpSocket::send_to(self, buf, address)
+  }
+
+  fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+    UdpSocket::recv_from(self, buf)
+  }
+}
+
+// One end of an in-memory link. Whatever one end sends, the other receives as if it came from
+// the sender's address, wherever it was addressed to.
+pub struct MemoryTransport {
+  address: SocketAddr,
+  outgoing: Mutex<Sender<(SocketAddr, Vec<u8>)>>,
+  incoming: Mutex<Receiver<(SocketAddr, Vec<u8>)>>
+}
+
+impl MemoryTransport {
+  pub fn pair(a: SocketAddr, b: SocketAddr) -> (MemoryTransport, MemoryTransport) {
+    let (a_outgoing, b_incoming) = channel();
+    let (b_outgoing, a_incoming) = channel();
+    (
+      MemoryTransport { address: a, outgoing: Mutex::new(a_outgoing), incoming: Mutex::new(a_incoming) },
+      MemoryTransport { address: b, outgoing: Mutex::new(b_outgoing), incoming: Mutex::new(b_incoming) }
+    )
+  }
+
+  pub fn address(&self) -> SocketAddr {
+    self.address
+  }
+
+  pub fn recv_timeout(&self, buf: &mut [u8], timeout: Duration) -> io::Result<(usize, SocketAddr)> {
+    let received = self.incoming.lock().expect("MemoryTransport::recv_timeout() tried to lock a poisoned mutex").recv_timeout(timeout);
+    match received {
+      Ok((src, data)) => Ok((Self::copy(&data, buf), src)),
+      Err(RecvTimeoutError::Timeout) => Err(io::Error::new(io::ErrorKind::TimedOut, "nothing received")),
+      Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "other end closed"))
+    }
+  }
+
+  // Like a UDP socket, anything that doesn't fit in the buffer is lost
+  fn copy(data: &[u8], buf: &mut [u8]) -> usize {
+    let amt = data.len().min(buf.len());
+    buf[..amt].copy_from_slice(&data[..amt]);
+    amt
+  }
+}
+
+impl Transport for MemoryTransport {
+  fn send_to(&self, buf: &[u8], _address: &str) -> io::Result<usize> {
+    let sender = self.outgoing.lock().expect("MemoryTransport::send_to() tried to lock a poisoned mutex");
+    match sender.send((self.address, buf.to_vec())) {
+      Ok(()) => Ok(buf.len()),
+      Err(_) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "other end closed"))
+    }
+  }
+
+  fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+    let received = self.incoming.lock().expect("MemoryTransport::recv_from() tried to lock a poisoned mutex").recv();
+    match received {
+      Ok((src, data)) => Ok((Self::copy(&data, buf), src)),
+      Err(_) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "other end closed"))
+    }
+  }
+}
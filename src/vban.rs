@@ -1,4 +1,7 @@
+use std::fmt;
+
 use packed_struct::prelude::*;
+use rt_state::{BusState, StripState};
 
 #[derive(PrimitiveEnum_u8, Debug, Clone, Copy, PartialEq)]
 pub enum VBANProtocol {
@@ -16,6 +19,101 @@ pub enum VoiceMeeterType {
     Potato = 3
 }
 
+impl VoiceMeeterType {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(VoiceMeeterType::Standard),
+            "banana" => Some(VoiceMeeterType::Banana),
+            "potato" => Some(VoiceMeeterType::Potato),
+            _ => None,
+        }
+    }
+
+    pub fn physical_strips(&self) -> usize {
+        match *self {
+            VoiceMeeterType::Standard => 2,
+            VoiceMeeterType::Banana => 3,
+            VoiceMeeterType::Potato => 5,
+        }
+    }
+
+    pub fn virtual_strips(&self) -> usize {
+        match *self {
+            VoiceMeeterType::Standard => 1,
+            VoiceMeeterType::Banana => 2,
+            VoiceMeeterType::Potato => 3,
+        }
+    }
+
+    pub fn physical_buses(&self) -> usize {
+        match *self {
+            VoiceMeeterType::Standard => 1,
+            VoiceMeeterType::Banana => 3,
+            VoiceMeeterType::Potato => 5,
+        }
+    }
+
+    pub fn virtual_buses(&self) -> usize {
+        match *self {
+            VoiceMeeterType::Standard => 1,
+            VoiceMeeterType::Banana => 2,
+            VoiceMeeterType::Potato => 3,
+        }
+    }
+
+    pub fn strips(&self) -> usize {
+        self.physical_strips() + self.virtual_strips()
+    }
+
+    pub fn buses(&self) -> usize {
+        self.physical_buses() + self.virtual_buses()
+    }
+
+    // Strips send to each bus through a gain layer of their own, except on Standard
+    pub fn gain_layers(&self) -> usize {
+        match *self {
+            VoiceMeeterType::Standard => 0,
+            _ => self.buses(),
+        }
+    }
+}
+
+// Stream names are NUL padded to 16 bytes, longer names are cut short
+pub fn stream_name(name: &str) -> [u8; 16] {
+    let mut out = [0; 16];
+    let len = name.len().min(16);
+    out[..len].copy_from_slice(&name.as_bytes()[..len]);
+    out
+}
+
+// Why a datagram couldn't be read as the VBAN packet it was meant to be
+#[derive(Debug, Clone, PartialEq)]
+pub enum VbanError {
+    TooShort { expected: usize, got: usize },
+    WrongLength { expected: usize, got: usize },
+    // Doesn't start with "VBAN"
+    NotVban,
+    // An RT packet from a VoiceMeeter edition we don't know the layout of
+    UnknownEdition(u8),
+    Unpack(PackingError)
+}
+
+impl fmt::Display for VbanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VbanError::TooShort { expected, got } => write!(f, "packet too short, {} bytes when at least {} are needed", got, expected),
+            VbanError::WrongLength { expected, got } => write!(f, "packet is {} bytes when it should be {}", got, expected),
+            VbanError::NotVban => write!(f, "not a VBAN packet"),
+            VbanError::UnknownEdition(edition) => write!(f, "unknown VoiceMeeter edition {}", edition),
+            VbanError::Unpack(ref e) => write!(f, "couldn't unpack the packet: {}", e),
+        }
+    }
+}
+
+fn packet_start(buf: &[u8], length: usize) -> Result<&[u8], VbanError> {
+    buf.get(0..length).ok_or(VbanError::TooShort { expected: length, got: buf.len() })
+}
+
 #[derive(PackedStruct, Debug, Clone, Copy, PartialEq)]
 #[packed_struct(endian="lsb", bit_numbering="msb0")]
 pub struct VBANHeader {
@@ -23,6 +121,17 @@ pub struct VBANHeader {
     pub protocol: u8
 }
 
+impl VBANHeader {
+    // Reads the start of any VBAN packet, anything after the header is left alone
+    pub fn parse(buf: &[u8]) -> Result<VBANHeader, VbanError> {
+        let header = VBANHeader::unpack_from_slice(packet_start(buf, 5)?).map_err(VbanError::Unpack)?;
+        if header.vban != *b"VBAN" {
+            return Err(VbanError::NotVban);
+        }
+        Ok(header)
+    }
+}
+
 #[derive(PackedStruct, Debug, Clone, Copy, PartialEq)]
 #[packed_struct(endian="lsb", bit_numbering="msb0")]
 pub struct VBANServiceHeader {
@@ -35,6 +144,13 @@ pub struct VBANServiceHeader {
     pub frame_id: u32
 }
 
+impl VBANServiceHeader {
+    pub fn parse(buf: &[u8]) -> Result<VBANServiceHeader, VbanError> {
+        VBANHeader::parse(buf)?;
+        VBANServiceHeader::unpack_from_slice(packet_start(buf, 28)?).map_err(VbanError::Unpack)
+    }
+}
+
 #[derive(PackedStruct, Debug, Clone, Copy, PartialEq)]
 #[packed_struct(endian="lsb", bit_numbering="msb0")]
 pub struct RegisterRT {
@@ -75,61 +191,256 @@ pub struct RTPacket {
 }
 
 impl RTPacket {
+    pub const LENGTH: usize = 1412;
+
+    // A packet from the given edition with every gain at 0 dB, every meter silent and every label
+    // empty, ready for the setters below and then pack()
+    pub fn new(edition: VoiceMeeterType, stream: &str, frame_id: u32) -> Self {
+        RTPacket {
+            header: VBANServiceHeader {
+                header: VBANHeader {
+                    vban: [0x56, 0x42, 0x41, 0x4e], // "VBAN"
+                    protocol: VBANProtocol::Service as u8
+                },
+                function: 0,
+                service: SERVICE_RT_PACKET,
+                additional_info: 0,
+                stream_name: stream_name(stream),
+                frame_id
+            },
+            voicemeeter_type: edition as u8,
+            reserved: 0,
+            buffer_size: 512,
+            voicemeeter_version_raw: [0; 4],
+            options: 0,
+            sample_rate: 48000,
+            input_levels_raw: [0; 34],
+            output_levels_raw: [0; 64],
+            transport: 0,
+            strip_state: [0; 8],
+            bus_state: [0; 8],
+            strip_gain_layer_1_raw: [0; 8],
+            strip_gain_layer_2_raw: [0; 8],
+            strip_gain_layer_3_raw: [0; 8],
+            strip_gain_layer_4_raw: [0; 8],
+            strip_gain_layer_5_raw: [0; 8],
+            strip_gain_layer_6_raw: [0; 8],
+            strip_gain_layer_7_raw: [0; 8],
+            strip_gain_layer_8_raw: [0; 8],
+            bus_gain_raw: [0; 8],
+            strip_labels_raw: [0; 480],
+            bus_labels_raw: [0; 480]
+        }
+    }
+
+    // The setters leave anything outside the packet alone rather than panic
+
+    fn raw_gain(gain: f32) -> i16 {
+        (gain * 100.0).round() as i16
+    }
+
+    pub fn set_input_gain(&mut self, strip: usize, gain: f32) {
+        self.set_input_gain_layer(0, strip, gain);
+    }
+
+    pub fn set_input_gain_layer(&mut self, layer: usize, strip: usize, gain: f32) {
+        let raw = match layer {
+            0 => &mut self.strip_gain_layer_1_raw,
+            1 => &mut self.strip_gain_layer_2_raw,
+            2 => &mut self.strip_gain_layer_3_raw,
+            3 => &mut self.strip_gain_layer_4_raw,
+            4 => &mut self.strip_gain_layer_5_raw,
+            5 => &mut self.strip_gain_layer_6_raw,
+            6 => &mut self.strip_gain_layer_7_raw,
+            7 => &mut self.strip_gain_layer_8_raw,
+            _ => return
+        };
+        if let Some(slot) = raw.get_mut(strip) {
+            *slot = Self::raw_gain(gain);
+        }
+    }
+
+    pub fn set_output_gain(&mut self, bus: usize, gain: f32) {
+        if let Some(slot) = self.bus_gain_raw.get_mut(bus) {
+            *slot = Self::raw_gain(gain);
+        }
+    }
+
+    pub fn set_strip_state(&mut self, strip: usize, state: StripState) {
+        if let Some(slot) = self.strip_state.get_mut(strip) {
+            *slot = state.bits;
+        }
+    }
+
+    pub fn set_bus_state(&mut self, bus: usize, state: BusState) {
+        if let Some(slot) = self.bus_state.get_mut(bus) {
+            *slot = state.bits;
+        }
+    }
+
+    // The inverse of normalize_level, with the level in dB
+    fn raw_level(level: f32) -> u16 {
+        ((1 << 16) - 1) as u16 - (level * -100.0).clamp(0.0, 65535.0) as u16
+    }
+
+    // Sets the first two channels of a strip, the ones input_levels reports. Does nothing
+    // until the packet has a known edition.
+    pub fn set_input_level(&mut self, strip: usize, level: f32) {
+        let edition = match self.edition() {
+            Ok(edition) => edition,
+            Err(_) => return
+        };
+        let first = if strip < edition.physical_strips() {
+            strip * 2
+        } else {
+            (edition.physical_strips() * 2) + ((strip - edition.physical_strips()) * 8)
+        };
+        for channel in first..(first + 2) {
+            if let Some(slot) = self.input_levels_raw.get_mut(channel) {
+                *slot = Self::raw_level(level);
+            }
+        }
+    }
+
+    pub fn set_output_level(&mut self, bus: usize, level: f32) {
+        for channel in (bus * 8)..((bus * 8) + 2) {
+            if let Some(slot) = self.output_levels_raw.get_mut(channel) {
+                *slot = Self::raw_level(level);
+            }
+        }
+    }
+
+    // Labels are NUL padded to 60 bytes, longer ones are cut short
+    fn set_label(raw_labels: &mut [u8; 480], index: usize, label: &str) {
+        if index >= 8 {
+            return;
+        }
+        let slot = &mut raw_labels[(index * 60)..((index * 60) + 60)];
+        let len = label.len().min(60);
+        slot.copy_from_slice(&[0; 60]);
+        slot[..len].copy_from_slice(&label.as_bytes()[..len]);
+    }
+
+    pub fn set_strip_label(&mut self, strip: usize, label: &str) {
+        Self::set_label(&mut self.strip_labels_raw, strip, label);
+    }
+
+    pub fn set_bus_label(&mut self, bus: usize, label: &str) {
+        Self::set_label(&mut self.bus_labels_raw, bus, label);
+    }
+
+    pub fn parse(buf: &[u8]) -> Result<RTPacket, VbanError> {
+        VBANServiceHeader::parse(buf)?;
+        if buf.len() != RTPacket::LENGTH {
+            return Err(VbanError::WrongLength { expected: RTPacket::LENGTH, got: buf.len() });
+        }
+        let packet = RTPacket::unpack_from_slice(buf).map_err(VbanError::Unpack)?;
+        packet.edition()?;
+        Ok(packet)
+    }
+
+    #[allow(clippy::needless_return)]
     pub fn voicemeeter_version(&self) -> [u8; 4] {
         let mut arr = self.voicemeeter_version_raw;
         arr.reverse();
         return arr;
     }
 
+    // Packets from parse always have a known edition. One built by hand with anything else has
+    // no strips or buses.
+    pub fn edition(&self) -> Result<VoiceMeeterType, VbanError> {
+        VoiceMeeterType::from_primitive(self.voicemeeter_type).ok_or(VbanError::UnknownEdition(self.voicemeeter_type))
+    }
+
+    pub fn strip_count(&self) -> usize {
+        self.edition().map_or(0, |edition| edition.strips())
+    }
+
+    pub fn bus_count(&self) -> usize {
+        self.edition().map_or(0, |edition| edition.buses())
+    }
+
+    #[allow(clippy::needless_return)]
     fn normalize_level(level: &u16) -> u16 {
         return ((1 << 16) - 1) as u16 - level;
     }
 
-    fn gains(raw_gains: [i16; 8]) -> [f32; 8] {
-      return raw_gains.map(|gain| (gain as f32 * 0.01));
+    fn gains(raw_gains: [i16; 8], count: usize) -> Vec<f32> {
+      raw_gains[0..count].iter().map(|gain| *gain as f32 * 0.01).collect()
+    }
+
+    pub fn input_gains(&self) -> Vec<f32> {
+        Self::gains(self.strip_gain_layer_1_raw, self.strip_count())
+    }
+
+    // Potato uses gain layers 1 to 8 as the per-bus level of each strip, index 0 is layer 1
+    pub fn input_gain_layer(&self, layer: usize) -> Option<Vec<f32>> {
+        let raw = match layer {
+            0 => self.strip_gain_layer_1_raw,
+            1 => self.strip_gain_layer_2_raw,
+            2 => self.strip_gain_layer_3_raw,
+            3 => self.strip_gain_layer_4_raw,
+            4 => self.strip_gain_layer_5_raw,
+            5 => self.strip_gain_layer_6_raw,
+            6 => self.strip_gain_layer_7_raw,
+            7 => self.strip_gain_layer_8_raw,
+            _ => return None
+        };
+        Some(Self::gains(raw, self.strip_count()))
+    }
+
+    pub fn input_gain_layers(&self) -> Vec<Vec<f32>> {
+        (0..8).map(|layer| self.input_gain_layer(layer).unwrap()).collect()
+    }
+
+    pub fn output_gains(&self) -> Vec<f32> {
+        Self::gains(self.bus_gain_raw, self.bus_count())
     }
 
-    pub fn input_gains(&self) -> [f32; 8] {
-        return Self::gains(self.strip_gain_layer_1_raw);
+    pub fn strip_states(&self) -> Vec<StripState> {
+        self.strip_state[0..self.strip_count()].iter().map(|bits| StripState::from_bits(*bits)).collect()
     }
 
-    pub fn output_gains(&self) -> [f32; 8] {
-        return Self::gains(self.bus_gain_raw);
+    pub fn bus_states(&self) -> Vec<BusState> {
+        self.bus_state[0..self.bus_count()].iter().map(|bits| BusState::from_bits(*bits)).collect()
     }
 
-    pub fn input_levels(&self) -> [[u16; 2]; 8] {
-        let physicals = &self.input_levels_raw[0..10];
-        let virtuals = &self.input_levels_raw[10..34];
+    // Physical strips are stereo, virtual strips carry 8 channels each; only the first two
+    // channels of every strip are reported
+    #[allow(clippy::needless_return)]
+    pub fn input_levels(&self) -> Vec<[u16; 2]> {
+        let edition = match self.edition() {
+            Ok(edition) => edition,
+            Err(_) => return Vec::new()
+        };
+        let physicals = &self.input_levels_raw[0..(edition.physical_strips() * 2)];
+        let virtuals = &self.input_levels_raw[(edition.physical_strips() * 2)..];
 
-        let mut out: [[u16; 2]; 8] = [[0; 2]; 8];
-        for i in 0..5 {
-            out[i] = [Self::normalize_level(&physicals[i * 2]), Self::normalize_level(&physicals[(i * 2) + 1])];
+        let mut out: Vec<[u16; 2]> = Vec::with_capacity(edition.strips());
+        for i in 0..edition.physical_strips() {
+            out.push([Self::normalize_level(&physicals[i * 2]), Self::normalize_level(&physicals[(i * 2) + 1])]);
         }
-        for i in 0..3 {
-            out[i + 5] = [Self::normalize_level(&virtuals[i * 8]), Self::normalize_level(&virtuals[(i * 8) + 1])];
+        for i in 0..edition.virtual_strips() {
+            out.push([Self::normalize_level(&virtuals[i * 8]), Self::normalize_level(&virtuals[(i * 8) + 1])]);
         }
         return out;
     }
 
-    pub fn output_levels(&self) -> [[u16; 2]; 8] {
-        let physicals = &self.output_levels_raw[0..40];
-        let virtuals = &self.output_levels_raw[40..64];
-
-        let mut out: [[u16; 2]; 8] = [[0; 2]; 8];
-        for i in 0..5 {
-            out[i] = [Self::normalize_level(&physicals[i * 8]), Self::normalize_level(&physicals[(i * 8) + 1])];
-        }
-        for i in 0..3 {
-            out[i + 5] = [Self::normalize_level(&virtuals[i * 8]), Self::normalize_level(&virtuals[(i * 8) + 1])];
+    // Every bus carries 8 channels, physical buses first
+    #[allow(clippy::needless_return)]
+    pub fn output_levels(&self) -> Vec<[u16; 2]> {
+        let mut out: Vec<[u16; 2]> = Vec::with_capacity(self.bus_count());
+        for i in 0..self.bus_count() {
+            out.push([Self::normalize_level(&self.output_levels_raw[i * 8]), Self::normalize_level(&self.output_levels_raw[(i * 8) + 1])]);
         }
         return out;
     }
 
-    fn levels_to_meters(levels: [[u16; 2]; 8]) -> [i16; 8] {
-      let mut out: [i16; 8] = [0; 8];
+    #[allow(clippy::needless_return)]
+    fn levels_to_meters(levels: Vec<[u16; 2]>) -> Vec<i16> {
+      let mut out: Vec<i16> = Vec::with_capacity(levels.len());
 
-      for i in 0..8 {
-          let raw_levels = levels[i];
+      for raw_levels in levels {
           let level_sum = raw_levels[0] as f32 + raw_levels[1] as f32;
           let level_avg = level_sum / 2.0;
           let mut level_normalised = level_avg * -0.01;
@@ -138,36 +449,40 @@ impl RTPacket {
           } else if level_normalised < -100.0 {
               level_normalised = -100.0;
           }
-          out[i] = (((level_normalised + 100.0) / 100.0) * 16.0) as i16;
+          out.push((((level_normalised + 100.0) / 100.0) * 16.0) as i16);
       }
 
       return out;
     }
 
-    pub fn input_meters(&self) -> [i16; 8] {
+    #[allow(clippy::needless_return)]
+    pub fn input_meters(&self) -> Vec<i16> {
       return Self::levels_to_meters(self.input_levels());
     }
 
-    pub fn output_meters(&self) -> [i16; 8] {
+    #[allow(clippy::needless_return)]
+    pub fn output_meters(&self) -> Vec<i16> {
       return Self::levels_to_meters(self.output_levels());
     }
 
-    fn format_labels(raw_labels: [u8; 480]) -> [String; 8] {
-      let mut out: [String; 8] = ["", "", "", "", "", "", "", ""].map(|s| s.to_string());
-      for i in 0..8 {
+    #[allow(clippy::needless_return)]
+    fn format_labels(raw_labels: [u8; 480], count: usize) -> Vec<String> {
+      let mut out: Vec<String> = Vec::with_capacity(count);
+      for i in 0..count {
           let raw_string = &raw_labels[(i * 60)..((i * 60) + 60)];
-          let label = std::str::from_utf8(raw_string).expect("invalid utf-8 sequence").to_string();
-          out[i] = label;
+          // Anything that isn't UTF-8 shows up as a replacement character rather than losing the label
+          let label = String::from_utf8_lossy(raw_string).to_string();
+          out.push(label);
       }
       return out;
     }
 
-    pub fn strip_labels(&self) -> [String; 8] {
-      return Self::format_labels(self.strip_labels_raw);
+    pub fn strip_labels(&self) -> Vec<String> {
+      Self::format_labels(self.strip_labels_raw, self.strip_count())
     }
 
-    pub fn bus_labels(&self) -> [String; 8] {
-      return Self::format_labels(self.bus_labels_raw);
+    pub fn bus_labels(&self) -> Vec<String> {
+      Self::format_labels(self.bus_labels_raw, self.bus_count())
     }
 }
 
@@ -191,6 +506,7 @@ pub struct MidiPacket {
     pub body: [u8; 3]
 }
 
+#[allow(clippy::needless_return, clippy::redundant_field_names)]
 impl MidiPacket {
   pub fn new(packet_data: [u8; 3], frame_id: u32) -> Self {
     return MidiPacket {
@@ -209,3 +525,310 @@ impl MidiPacket {
     };
   }
 }
+
+#[derive(PackedStruct, Debug, Clone, Copy, PartialEq)]
+#[packed_struct(endian="lsb", bit_numbering="msb0")]
+pub struct VBANTextHeader {
+    #[packed_field(element_size_bytes="5")]
+    pub header: VBANHeader,
+    pub bitmode: u8,
+    pub channels: u8,
+    pub data_format: u8, // 0x10 for utf-8 text
+    pub stream_name: [u8; 16],
+    pub frame_id: u32
+}
+
+pub struct TextPacket {
+    pub header: VBANTextHeader,
+    pub body: String
+}
+
+impl TextPacket {
+  pub fn new(text: &str, frame_id: u32) -> Self {
+    TextPacket {
+      header: VBANTextHeader {
+        header: VBANHeader {
+          vban: [0x56, 0x42, 0x41, 0x4e], // "VBAN"
+          protocol: VBANProtocol::Txt as u8
+        },
+        bitmode: 0,
+        channels: 0,
+        data_format: 0x10, // 0x10 for UTF-8 text
+        stream_name: [0x43, 0x6f, 0x6d, 0x6d, 0x61, 0x6e, 0x64, 0x31, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // "Command1"
+        frame_id
+      },
+      body: text.to_string()
+    }
+  }
+
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let mut out = self.header.pack().expect("couldn't pack text header").to_vec();
+    out.extend_from_slice(self.body.as_bytes());
+    out
+  }
+}
+
+// Service types, as carried in a service header's service byte
+pub const SERVICE_IDENTIFICATION: u8 = 0;
+pub const SERVICE_RT_REGISTER: u8 = 32;
+pub const SERVICE_RT_PACKET: u8 = 33;
+// Set in a service header's function byte on a reply
+pub const SERVICE_REPLY: u8 = 0x80;
+
+// What a ping says a device is and can do, only the ones this crate has a use for
+pub const PING_TYPE_RECEPTOR: u32 = 0x0000_0001;
+pub const PING_TYPE_TRANSMITTER: u32 = 0x0000_0002;
+pub const PING_TYPE_VIRTUAL_MIXER: u32 = 0x0000_0020;
+pub const PING_FEATURE_SERIAL: u32 = 0x0000_0100;
+pub const PING_FEATURE_MIDI: u32 = 0x0000_0300;
+pub const PING_FEATURE_TXT: u32 = 0x0001_0000;
+
+// The PING0 body a host sends to introduce itself, both asking and answering
+#[derive(PackedStruct, Debug, Clone, Copy, PartialEq)]
+#[packed_struct(endian="lsb", bit_numbering="msb0")]
+pub struct VBANPing0 {
+    pub device_type: u32,
+    pub features: u32,
+    pub features_ex: u32,
+    pub preferred_rate: u32,
+    pub min_rate: u32,
+    pub max_rate: u32,
+    pub color_rgb: u32,
+    version_raw: [u8; 4],
+    pub gps_position: [u8; 8],
+    pub user_position: [u8; 8],
+    pub language_code: [u8; 8],
+    pub reserved: [u8; 8],
+    pub reserved_ex: [u8; 64],
+    pub distant_ip: [u8; 32],
+    pub distant_port: u16,
+    pub distant_reserved: u16,
+    device_name_raw: [u8; 64],
+    manufacturer_name_raw: [u8; 64],
+    application_name_raw: [u8; 64],
+    host_name_raw: [u8; 64],
+    user_name_raw: [u8; 128],
+    user_comment_raw: [u8; 128]
+}
+
+// Text fields are zero padded, and cut short if they don't fit
+fn fill_text(field: &mut [u8], text: &str) {
+    let len = text.len().min(field.len());
+    field.iter_mut().for_each(|byte| *byte = 0);
+    field[..len].copy_from_slice(&text.as_bytes()[..len]);
+}
+
+fn field_text(field: &[u8]) -> String {
+    let end = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+impl VBANPing0 {
+    pub fn new(device_type: u32, features: u32, version: [u8; 4]) -> Self {
+        let mut version_raw = version;
+        version_raw.reverse();
+        VBANPing0 {
+            device_type,
+            features,
+            features_ex: 0,
+            preferred_rate: 48000,
+            min_rate: 0,
+            max_rate: 0,
+            color_rgb: 0,
+            version_raw,
+            gps_position: [0; 8],
+            user_position: [0; 8],
+            language_code: [0; 8],
+            reserved: [0; 8],
+            reserved_ex: [0; 64],
+            distant_ip: [0; 32],
+            distant_port: 0,
+            distant_reserved: 0,
+            device_name_raw: [0; 64],
+            manufacturer_name_raw: [0; 64],
+            application_name_raw: [0; 64],
+            host_name_raw: [0; 64],
+            user_name_raw: [0; 128],
+            user_comment_raw: [0; 128]
+        }
+    }
+
+    pub fn with_names(mut self, device: &str, manufacturer: &str, application: &str, host: &str) -> Self {
+        fill_text(&mut self.device_name_raw, device);
+        fill_text(&mut self.manufacturer_name_raw, manufacturer);
+        fill_text(&mut self.application_name_raw, application);
+        fill_text(&mut self.host_name_raw, host);
+        self
+    }
+
+    // Most significant part first, the same way round as an RT packet's VoiceMeeter version
+    pub fn version(&self) -> [u8; 4] {
+        let mut version = self.version_raw;
+        version.reverse();
+        version
+    }
+
+    pub fn device_name(&self) -> String {
+        field_text(&self.device_name_raw)
+    }
+
+    pub fn manufacturer_name(&self) -> String {
+        field_text(&self.manufacturer_name_raw)
+    }
+
+    pub fn application_name(&self) -> String {
+        field_text(&self.application_name_raw)
+    }
+
+    pub fn host_name(&self) -> String {
+        field_text(&self.host_name_raw)
+    }
+
+    pub fn user_name(&self) -> String {
+        field_text(&self.user_name_raw)
+    }
+}
+
+#[derive(PackedStruct, Debug, Clone, Copy, PartialEq)]
+#[packed_struct(endian="lsb", bit_numbering="msb0")]
+pub struct PingPacket {
+    #[packed_field(element_size_bytes="28")]
+    pub header: VBANServiceHeader,
+    #[packed_field(element_size_bytes="676")]
+    pub body: VBANPing0
+}
+
+impl PingPacket {
+    pub const LENGTH: usize = 704;
+
+    pub fn new(body: VBANPing0, reply: bool, frame_id: u32) -> Self {
+        PingPacket {
+            header: VBANServiceHeader {
+                header: VBANHeader {
+                    vban: [0x56, 0x42, 0x41, 0x4e], // "VBAN"
+                    protocol: VBANProtocol::Service as u8
+                },
+                function: if reply { SERVICE_REPLY } else { 0 },
+                service: SERVICE_IDENTIFICATION,
+                additional_info: 0,
+                stream_name: stream_name("VBAN Service"),
+                frame_id
+            },
+            body
+        }
+    }
+
+    // Anything after the PING0 body is left alone, later versions of it may be longer
+    pub fn parse(buf: &[u8]) -> Result<PingPacket, VbanError> {
+        VBANServiceHeader::parse(buf)?;
+        PingPacket::unpack_from_slice(packet_start(buf, PingPacket::LENGTH)?).map_err(VbanError::Unpack)
+    }
+
+    // Whether a service datagram is a ping or an answer to one, going by the header alone
+    pub fn is_ping(buf: &[u8]) -> bool {
+        match VBANServiceHeader::parse(buf) {
+            Ok(header) => header.header.protocol == VBANProtocol::Service as u8 && header.service == SERVICE_IDENTIFICATION,
+            Err(_) => false
+        }
+    }
+
+    pub fn is_reply(&self) -> bool {
+        self.header.function & SERVICE_REPLY == SERVICE_REPLY
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoiceMeeterChannel {
+    Strip(usize),
+    Bus(usize)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoiceMeeterParameter {
+    Gain(f32),
+    GainLayer(usize, f32),
+    Pan(f32),
+    EqGain(usize, f32),
+    Reverb(f32),
+    Delay(f32),
+    Mute(bool),
+    Solo(bool),
+    Mono(bool)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceMeeterCommand {
+    pub channel: VoiceMeeterChannel,
+    pub parameter: VoiceMeeterParameter
+}
+
+impl VoiceMeeterCommand {
+  pub fn new(channel: VoiceMeeterChannel, parameter: VoiceMeeterParameter) -> Self {
+    VoiceMeeterCommand { channel, parameter }
+  }
+
+  // Renders the command as a VoiceMeeter script statement, e.g. `Strip[3].Gain = -6.5;`
+  pub fn as_script(&self) -> String {
+    let channel = match self.channel {
+      VoiceMeeterChannel::Strip(id) => format!("Strip[{}]", id),
+      VoiceMeeterChannel::Bus(id) => format!("Bus[{}]", id)
+    };
+    let parameter = match self.parameter {
+      VoiceMeeterParameter::Gain(gain) => format!("Gain = {:.1}", gain),
+      VoiceMeeterParameter::GainLayer(layer, gain) => format!("GainLayer[{}] = {:.1}", layer, gain),
+      VoiceMeeterParameter::Pan(pan) => format!("Pan_x = {:.2}", pan),
+      VoiceMeeterParameter::EqGain(band, gain) => format!("EQGain{} = {:.1}", band + 1, gain),
+      VoiceMeeterParameter::Reverb(level) => format!("Reverb = {:.1}", level),
+      VoiceMeeterParameter::Delay(level) => format!("Delay = {:.1}", level),
+      VoiceMeeterParameter::Mute(on) => format!("Mute = {}", on as u8),
+      VoiceMeeterParameter::Solo(on) => format!("Solo = {}", on as u8),
+      VoiceMeeterParameter::Mono(on) => format!("Mono = {}", on as u8)
+    };
+    format!("{}.{};", channel, parameter)
+  }
+
+  // Reads a single script statement back, the trailing ';' is optional. Names are matched the way
+  // VoiceMeeter matches them, ignoring case.
+  pub fn parse(statement: &str) -> Option<Self> {
+    let statement = statement.trim().trim_end_matches(';');
+    let mut sides = statement.splitn(2, '=');
+    let target = sides.next()?.trim();
+    let value = sides.next()?.trim();
+    let mut names = target.splitn(2, '.');
+    let channel_name = names.next()?.trim();
+    let parameter_name = names.next()?.trim().to_ascii_lowercase();
+
+    let channel = if let Some(id) = script_index(channel_name, "strip") {
+      VoiceMeeterChannel::Strip(id)
+    } else if let Some(id) = script_index(channel_name, "bus") {
+      VoiceMeeterChannel::Bus(id)
+    } else {
+      return None;
+    };
+
+    let number: f32 = value.parse().ok()?;
+    let parameter = match parameter_name.as_str() {
+      "gain" => VoiceMeeterParameter::Gain(number),
+      "pan_x" => VoiceMeeterParameter::Pan(number),
+      "eqgain1" => VoiceMeeterParameter::EqGain(0, number),
+      "eqgain2" => VoiceMeeterParameter::EqGain(1, number),
+      "eqgain3" => VoiceMeeterParameter::EqGain(2, number),
+      "reverb" => VoiceMeeterParameter::Reverb(number),
+      "delay" => VoiceMeeterParameter::Delay(number),
+      "mute" => VoiceMeeterParameter::Mute(number != 0.0),
+      "solo" => VoiceMeeterParameter::Solo(number != 0.0),
+      "mono" => VoiceMeeterParameter::Mono(number != 0.0),
+      name => VoiceMeeterParameter::GainLayer(script_index(name, "gainlayer")?, number)
+    };
+    Some(VoiceMeeterCommand::new(channel, parameter))
+  }
+}
+
+// The index out of a name like `Strip[3]`, if the name before it matches
+pub fn script_index(text: &str, name: &str) -> Option<usize> {
+  let open = text.find('[')?;
+  if !text[..open].trim().eq_ignore_ascii_case(name) || !text.ends_with(']') {
+    return None;
+  }
+  text[(open + 1)..(text.len() - 1)].trim().parse().ok()
+}
@@ -1,10 +1,12 @@
-extern crate hex;
+use std::fmt;
 
+#[derive(Clone, PartialEq)]
 pub struct XctrlMeter {
   pub id: u8,
   pub level: u8
 }
 
+#[allow(clippy::needless_return)]
 impl XctrlMeter {
   pub fn as_bytes(&self) -> [u8; 4] {
     let normalised_level: u8 = (self.level / 2) + (self.id * 0x10);
@@ -16,11 +18,13 @@ impl XctrlMeter {
   }
 }
 
+#[derive(Clone, PartialEq)]
 pub struct XctrlFader {
   pub id: u8,
   pub level: u16
 }
 
+#[allow(clippy::needless_return)]
 impl XctrlFader {
   pub fn as_bytes(&self) -> [u8; 5] {
     let upper: u8 = (self.level & 0x00ff) as u8;
@@ -33,6 +37,52 @@ impl XctrlFader {
   }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum XctrlEncoderRingMode {
+  SingleDot = 0x00,
+  BoostCut = 0x10,
+  Wrap = 0x20,
+  Spread = 0x30
+}
+
+#[derive(Clone, PartialEq)]
+pub struct XctrlEncoderRing {
+  pub id: u8,
+  pub mode: u8,
+  pub value: u8,
+  pub center: bool
+}
+
+impl XctrlEncoderRing {
+  // Position runs from 0.0 (fully anticlockwise) to 1.0 (fully clockwise) across the 11 ring LEDs
+  pub fn new(id: u8, mode: XctrlEncoderRingMode, position: f32) -> XctrlEncoderRing {
+    let position = position.clamp(0.0, 1.0);
+    let value = match mode {
+      XctrlEncoderRingMode::Spread => 1 + (position * 5.0).round() as u8,
+      _ => 1 + (position * 10.0).round() as u8
+    };
+    XctrlEncoderRing {
+      id,
+      mode: mode as u8,
+      value,
+      center: false
+    }
+  }
+
+  pub fn off(id: u8) -> XctrlEncoderRing {
+    XctrlEncoderRing { id, mode: XctrlEncoderRingMode::SingleDot as u8, value: 0, center: false }
+  }
+
+  pub fn as_bytes(&self) -> [u8; 5] {
+    let center: u8 = if self.center { 0x40 } else { 0x00 };
+    [0xf0, 0xb0, 0x30 + self.id, center + self.mode + (self.value & 0x0f), 0xf7]
+  }
+
+  pub fn as_str(&self) -> String {
+    hex::encode(self.as_bytes())
+  }
+}
+
 #[derive(Copy, Clone)]
 pub enum XctrlButtonType {
   Rec = 0x00, // 8
@@ -77,11 +127,13 @@ pub enum XctrlButtonType {
   Enter = 0x53
 }
 
+#[derive(Clone, PartialEq)]
 pub struct XctrlButton {
   pub id: u8,
   pub state: u8
 }
 
+#[allow(clippy::needless_return)]
 impl XctrlButton {
   pub fn as_bytes(&self) -> [u8; 5] {
     return [0xf0, 0x90, self.id, self.state, 0xf7];
@@ -92,6 +144,7 @@ impl XctrlButton {
   }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum XctrlDisplayColor {
   Off = 0x00,
   Red = 0x01,
@@ -110,6 +163,31 @@ pub enum XctrlDisplayColor {
   WhiteInv = 0x47
 }
 
+impl XctrlDisplayColor {
+  // Names as written in the config file, "-inverted" picks dark text on a lit background
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "off" => Some(XctrlDisplayColor::Off),
+      "red" => Some(XctrlDisplayColor::Red),
+      "green" => Some(XctrlDisplayColor::Green),
+      "yellow" => Some(XctrlDisplayColor::Yellow),
+      "blue" => Some(XctrlDisplayColor::Blue),
+      "pink" => Some(XctrlDisplayColor::Pink),
+      "cyan" => Some(XctrlDisplayColor::Cyan),
+      "white" => Some(XctrlDisplayColor::White),
+      "red-inverted" => Some(XctrlDisplayColor::RedInv),
+      "green-inverted" => Some(XctrlDisplayColor::GreenInv),
+      "yellow-inverted" => Some(XctrlDisplayColor::YellowInv),
+      "blue-inverted" => Some(XctrlDisplayColor::BlueInv),
+      "pink-inverted" => Some(XctrlDisplayColor::PinkInv),
+      "cyan-inverted" => Some(XctrlDisplayColor::CyanInv),
+      "white-inverted" => Some(XctrlDisplayColor::WhiteInv),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Clone, PartialEq)]
 pub struct XctrlDisplay {
   pub id: u8,
   pub color: u8,
@@ -117,6 +195,7 @@ pub struct XctrlDisplay {
   pub bottom_text: [u8; 7]
 }
 
+#[allow(clippy::needless_return, clippy::manual_memcpy)]
 impl XctrlDisplay {
   pub fn new(id: u8, color: XctrlDisplayColor, top_text: &[u8], bottom_text: &[u8]) -> XctrlDisplay {
       let mut top: [u8; 7] = [0; 7];
@@ -128,7 +207,7 @@ impl XctrlDisplay {
       }
 
       return XctrlDisplay {
-          id: id,
+          id,
           color: color as u8,
           top_text: top,
           bottom_text: bottom
@@ -158,14 +237,80 @@ impl XctrlDisplay {
 
 }
 
+#[derive(Clone, PartialEq)]
 pub struct XctrlState {
     pub displays: [XctrlDisplay; 8],
     pub meters: [XctrlMeter; 8],
     pub faders: [XctrlFader; 9],
-    pub buttons: [XctrlButton; 84]
+    pub rings: [XctrlEncoderRing; 8],
+    pub buttons: [XctrlButton; 84],
+    // Faders with a hand on them, these must not be driven by the motors
+    pub touched: [bool; 9]
 }
 
 impl XctrlState {
+  // A surface with every display switched off
+  pub fn blank() -> Self {
+    let mut state = Self::new();
+    for display in state.displays.iter_mut() {
+      display.color = XctrlDisplayColor::Off as u8;
+    }
+    state
+  }
+
+  // Copies one channel strip of another surface into a strip of this one: its display, meter,
+  // fader, encoder ring and the rows of buttons above the fader
+  pub fn copy_strip(&mut self, from: &XctrlState, from_index: usize, to_index: usize) {
+    let id = to_index as u8;
+    self.displays[to_index] = XctrlDisplay { id, ..from.displays[from_index].clone() };
+    self.meters[to_index] = XctrlMeter { id, ..from.meters[from_index].clone() };
+    self.faders[to_index] = XctrlFader { id, ..from.faders[from_index].clone() };
+    self.rings[to_index] = XctrlEncoderRing { id, ..from.rings[from_index].clone() };
+    for row in [XctrlButtonType::Rec, XctrlButtonType::Solo, XctrlButtonType::Mute, XctrlButtonType::Select, XctrlButtonType::Encoder].iter() {
+      let button = *row as usize + to_index;
+      self.buttons[button] = XctrlButton { id: button as u8, state: from.buttons[*row as usize + from_index].state };
+    }
+  }
+
+  // Messages for everything that differs from what the device is already showing, grouped into
+  // displays, controls and buttons so each group fits a datagram. With nothing sent yet the whole
+  // surface is included. Touched faders are always left out so the motors don't fight the user.
+  pub fn changes(&self, shown: Option<&XctrlState>) -> Vec<Vec<u8>> {
+    let mut displays: Vec<u8> = Vec::new();
+    for (i, display) in self.displays.iter().enumerate() {
+      if shown.map(|shown| shown.displays[i] != *display).unwrap_or(true) {
+        displays.extend_from_slice(&display.as_bytes());
+      }
+    }
+
+    let mut controls: Vec<u8> = Vec::new();
+    for (i, meter) in self.meters.iter().enumerate() {
+      if shown.map(|shown| shown.meters[i] != *meter).unwrap_or(true) {
+        controls.extend_from_slice(&meter.as_bytes());
+      }
+    }
+    for (i, ring) in self.rings.iter().enumerate() {
+      if shown.map(|shown| shown.rings[i] != *ring).unwrap_or(true) {
+        controls.extend_from_slice(&ring.as_bytes());
+      }
+    }
+    for (i, fader) in self.faders.iter().enumerate() {
+      if !self.touched[i] && shown.map(|shown| shown.faders[i] != *fader).unwrap_or(true) {
+        controls.extend_from_slice(&fader.as_bytes());
+      }
+    }
+
+    let mut buttons: Vec<u8> = Vec::new();
+    for (i, button) in self.buttons.iter().enumerate() {
+      if shown.map(|shown| shown.buttons[i] != *button).unwrap_or(true) {
+        buttons.extend_from_slice(&button.as_bytes());
+      }
+    }
+
+    vec![displays, controls, buttons].into_iter().filter(|group| !group.is_empty()).collect()
+  }
+
+  #[allow(clippy::needless_return, clippy::new_without_default, clippy::identity_op)]
   pub fn new() -> Self {
     return XctrlState {
       displays: [
@@ -199,6 +344,16 @@ impl XctrlState {
         XctrlFader { id: 7, level: 0 },
         XctrlFader { id: 8, level: 0 },
       ],
+      rings: [
+        XctrlEncoderRing::off(0),
+        XctrlEncoderRing::off(1),
+        XctrlEncoderRing::off(2),
+        XctrlEncoderRing::off(3),
+        XctrlEncoderRing::off(4),
+        XctrlEncoderRing::off(5),
+        XctrlEncoderRing::off(6),
+        XctrlEncoderRing::off(7),
+      ],
       buttons: [
         XctrlButton { id: XctrlButtonType::Rec as u8 + 0, state: 0 },
         XctrlButton { id: XctrlButtonType::Rec as u8 + 1, state: 0 },
@@ -284,36 +439,175 @@ impl XctrlState {
         XctrlButton { id: XctrlButtonType::Undo as u8, state: 0 },
         XctrlButton { id: XctrlButtonType::Cancel as u8, state: 0 },
         XctrlButton { id: XctrlButtonType::Enter as u8, state: 0 },
-      ]
+      ],
+      touched: [false; 9]
     };
   }
 }
 
-#[repr(u8)]
-#[derive(Clone, Copy)]
-pub enum XctrlInterface {
-  Encoder = 0xb,
-  Button = 0x9,
-  Fader = 0xe,
-  Unknown = 0x00
+// Sent by the surface every couple of seconds to look for the bridge
+pub const XCTRL_PROBE: [u8; 8] = [0xf0, 0x00, 0x20, 0x32, 0x58, 0x54, 0x00, 0xf7];
+
+// Sent back to the surface in reply to its probe
+pub const XCTRL_HANDSHAKE_REPLY: [u8; 7] = [0xf0, 0x00, 0x00, 0x66, 0x14, 0x00, 0xf7];
+
+const FADER_TOUCH_NOTE: u8 = 0x68;
+const FOOTSWITCH_NOTE: u8 = 0x66;
+const ENCODER_CONTROL: u8 = 0x10;
+const JOG_WHEEL_CONTROL: u8 = 0x3c;
+
+// Why a datagram from a surface couldn't be read
+#[derive(Clone, Debug, PartialEq)]
+pub enum XctrlError {
+  Empty,
+  // A message cut off before all of its data bytes
+  Truncated(Vec<u8>),
+  // A SysEx message with no end byte
+  Unterminated(Vec<u8>),
+  // A data byte with no status byte in front of it
+  StrayData(u8)
 }
 
-impl XctrlInterface {
-  pub fn from(val: u8) -> Self {
-      match val {
-          0xb => return XctrlInterface::Encoder,
-          0x9 => return XctrlInterface::Button,
-          0xe => return XctrlInterface::Fader,
-          _ => return XctrlInterface::Unknown,
-      };
+impl fmt::Display for XctrlError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      XctrlError::Empty => write!(f, "empty datagram"),
+      XctrlError::Truncated(ref buf) => write!(f, "message cut short: {}", hex::encode(buf)),
+      XctrlError::Unterminated(ref buf) => write!(f, "SysEx message with no end: {}", hex::encode(buf)),
+      XctrlError::StrayData(byte) => write!(f, "data byte {:02x} without a status byte", byte),
+    }
   }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub enum XctrlMessage {
+  Probe,
+  DeviceId { serial: String },
+  Button { id: u8, pressed: bool },
+  FaderTouch { id: u8, touched: bool },
+  Fader { id: u8, level: u16 },
+  Encoder { id: u8, delta: i8 },
+  JogWheel { delta: i8 },
+  Footswitch { id: u8, pressed: bool },
+  Unknown(Vec<u8>)
+}
 
-#[derive(Clone, Copy)]
-pub struct XctrlStateUpdate {
-  pub interface_type: XctrlInterface,
-  pub id: u8,
-  pub value: u16,
-  pub raw_message: [u8; 3]
+impl XctrlMessage {
+  // Splits a datagram into its individual messages, the surface can batch several into one
+  pub fn parse(buf: &[u8]) -> Vec<XctrlMessage> {
+    let mut out = Vec::new();
+    let mut offset = 0;
+    while offset < buf.len() {
+      let length = Self::message_length(&buf[offset..]);
+      out.push(Self::decode(&buf[offset..(offset + length)]));
+      offset += length;
+    }
+    out
+  }
+
+  // As parse, but a datagram with anything malformed in it is refused as a whole. Well formed
+  // messages the bridge has no use for still come back as Unknown.
+  pub fn try_parse(buf: &[u8]) -> Result<Vec<XctrlMessage>, XctrlError> {
+    if buf.is_empty() {
+      return Err(XctrlError::Empty);
+    }
+    let mut out = Vec::new();
+    let mut offset = 0;
+    while offset < buf.len() {
+      let length = Self::message_length(&buf[offset..]);
+      out.push(Self::try_decode(&buf[offset..(offset + length)])?);
+      offset += length;
+    }
+    Ok(out)
+  }
+
+  fn message_length(buf: &[u8]) -> usize {
+    let length = match buf[0] {
+      0xf0 => match buf.iter().position(|byte| *byte == 0xf7) {
+        Some(end) => end + 1,
+        None => buf.len()
+      },
+      status if status & 0xe0 == 0xc0 => 2, // program change and channel pressure
+      status if status & 0x80 == 0x80 => 3,
+      _ => 1
+    };
+    std::cmp::min(length, buf.len())
+  }
+
+  // Relative controls set bit 6 for anticlockwise movement, the rest is the step count
+  fn relative_delta(value: u8) -> i8 {
+    let steps = (value & 0x3f) as i8;
+    if value & 0x40 == 0x40 {
+      return -steps;
+    }
+    steps
+  }
+
+  fn relative_value(delta: i8) -> u8 {
+    let steps = delta.unsigned_abs() & 0x3f;
+    if delta < 0 {
+      return steps | 0x40;
+    }
+    steps
+  }
+
+  // Decodes a single message, checking it's all there first
+  pub fn try_decode(buf: &[u8]) -> Result<XctrlMessage, XctrlError> {
+    let status = match buf.first() {
+      Some(status) => *status,
+      None => return Err(XctrlError::Empty)
+    };
+    if status == 0xf0 && buf.last() != Some(&0xf7) {
+      return Err(XctrlError::Unterminated(buf.to_vec()));
+    }
+    if status & 0x80 == 0 {
+      return Err(XctrlError::StrayData(status));
+    }
+    if status != 0xf0 && buf.len() < Self::message_length(&[status, 0, 0]) {
+      return Err(XctrlError::Truncated(buf.to_vec()));
+    }
+    Ok(Self::decode(buf))
+  }
+
+  // Decodes a single message
+  pub fn decode(buf: &[u8]) -> XctrlMessage {
+    match *buf {
+      ref probe if *probe == XCTRL_PROBE => XctrlMessage::Probe,
+      [0xf0, 0x00, 0x00, 0x66, 0x58, 0x01, ref serial @ .., 0xf7] => {
+        XctrlMessage::DeviceId { serial: String::from_utf8_lossy(serial).to_string() }
+      },
+      [status, id, value] if status & 0xf0 == 0x90 => {
+        let on = value >= 0x40;
+        match id {
+          FADER_TOUCH_NOTE..=0x70 => XctrlMessage::FaderTouch { id: id - FADER_TOUCH_NOTE, touched: on },
+          FOOTSWITCH_NOTE..=0x67 => XctrlMessage::Footswitch { id: id - FOOTSWITCH_NOTE, pressed: on },
+          _ => XctrlMessage::Button { id, pressed: on }
+        }
+      },
+      [status, lower, upper] if status & 0xf0 == 0xe0 => {
+        XctrlMessage::Fader { id: status & 0x0f, level: ((upper as u16) << 8) + lower as u16 }
+      },
+      [status, control, value] if status & 0xf0 == 0xb0 => {
+        match control {
+          ENCODER_CONTROL..=0x17 => XctrlMessage::Encoder { id: control - ENCODER_CONTROL, delta: Self::relative_delta(value) },
+          JOG_WHEEL_CONTROL => XctrlMessage::JogWheel { delta: Self::relative_delta(value) },
+          _ => XctrlMessage::Unknown(buf.to_vec())
+        }
+      },
+      _ => XctrlMessage::Unknown(buf.to_vec())
+    }
+  }
+
+  // The three byte MIDI form of a control message, as the surface sent it
+  pub fn as_midi(&self) -> Option<[u8; 3]> {
+    match *self {
+      XctrlMessage::Button { id, pressed } => Some([0x90, id, if pressed { 127 } else { 0 }]),
+      XctrlMessage::FaderTouch { id, touched } => Some([0x90, FADER_TOUCH_NOTE + id, if touched { 127 } else { 0 }]),
+      XctrlMessage::Footswitch { id, pressed } => Some([0x90, FOOTSWITCH_NOTE + id, if pressed { 127 } else { 0 }]),
+      XctrlMessage::Fader { id, level } => Some([0xe0 + id, (level & 0x00ff) as u8, (level >> 8) as u8]),
+      XctrlMessage::Encoder { id, delta } => Some([0xb0, ENCODER_CONTROL + id, Self::relative_value(delta)]),
+      XctrlMessage::JogWheel { delta } => Some([0xb0, JOG_WHEEL_CONTROL, Self::relative_value(delta)]),
+      _ => None
+    }
+  }
 }
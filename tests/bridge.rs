@@ -0,0 +1,423 @@
+extern crate packed_struct;
+extern crate vban_xctrl;
+
+use packed_struct::prelude::*;
+use vban_xctrl::*;
+
+mod common;
+
+use common::contains;
+
+const XTOUCH: &str = "192.168.1.20:10111";
+const EXTENDER: &str = "192.168.1.21:10111";
+
+fn rt_packet(gain: f32) -> RTPacket {
+    RTPacket::unpack(&common::rt_packet_bytes(gain)).unwrap()
+}
+
+fn xctrl_bytes(outputs: &[BridgeOutput], address: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for output in outputs {
+        if let BridgeOutput::Xctrl(to, data) = output {
+            if to == address {
+                bytes.extend_from_slice(data);
+            }
+        }
+    }
+    bytes
+}
+
+#[test]
+fn answers_the_handshake_probe() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Probe));
+    assert_eq!(out, vec![BridgeOutput::Xctrl(XTOUCH.to_string(), XCTRL_HANDSHAKE_REPLY.to_vec())]);
+}
+
+#[test]
+fn shows_labels_gains_and_flags() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    assert!(bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0)))).is_empty());
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+
+    let label = XctrlDisplay::new(0, XctrlDisplayColor::Green, b"Mic\0\0\0\0", &[0; 7]);
+    assert!(contains(&sent, &label.as_bytes()));
+    // Banana only has 5 strips, the rest are blanked
+    let blank = XctrlDisplay::new(5, XctrlDisplayColor::Off, &[0; 7], &[0; 7]);
+    assert!(contains(&sent, &blank.as_bytes()));
+
+    let fader = XctrlFader { id: 3, level: FaderRange::default().level(0.0) };
+    assert!(contains(&sent, &fader.as_bytes()));
+
+    let mute = XctrlButton { id: XctrlButtonType::Mute as u8, state: 127 };
+    assert!(contains(&sent, &mute.as_bytes()));
+    let unmuted = XctrlButton { id: XctrlButtonType::Mute as u8 + 1, state: 0 };
+    assert!(contains(&sent, &unmuted.as_bytes()));
+}
+
+#[test]
+fn only_sends_what_changed() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+    assert!(!bridge.refresh().is_empty());
+    assert!(bridge.refresh().is_empty());
+
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(-10.0))));
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+    let fader = XctrlFader { id: 0, level: FaderRange::default().level(-10.0) };
+    assert!(contains(&sent, &fader.as_bytes()));
+    assert!(!contains(&sent, &XctrlDisplay::new(0, XctrlDisplayColor::Green, b"Mic\0\0\0\0", &[0; 7]).as_bytes()));
+}
+
+#[test]
+fn fader_bank_buttons_change_page() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH, EXTENDER], OutputMode::Midi)).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+
+    // The Extender carries on from the X-Touch, so it shows the buses
+    let sent = xctrl_bytes(&bridge.refresh(), EXTENDER);
+    assert!(contains(&sent, &XctrlDisplay::new(0, XctrlDisplayColor::Blue, b"Main\0\0\0", &[0; 7]).as_bytes()));
+
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Button { id: XctrlButtonType::FaderBank as u8 + 1, pressed: true }));
+    assert_eq!(bridge.position, 8);
+    let out = bridge.refresh();
+    assert!(contains(&xctrl_bytes(&out, XTOUCH), &XctrlDisplay::new(0, XctrlDisplayColor::Blue, b"Main\0\0\0", &[0; 7]).as_bytes()));
+    // Past the last bank there's nothing to show
+    assert!(contains(&xctrl_bytes(&out, EXTENDER), &XctrlDisplay::new(0, XctrlDisplayColor::Off, &[0; 7], &[0; 7]).as_bytes()));
+}
+
+#[test]
+fn midi_mode_offsets_the_outputs_page() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 2, level: 0x1234 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(MidiPacket::new([0xe2, 0x34, 0x12], 1).pack().unwrap().to_vec())]);
+
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Button { id: XctrlButtonType::FaderBank as u8 + 1, pressed: true }));
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 2, level: 0x1234 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(MidiPacket::new([0xea, 0x34, 0x12], 3).pack().unwrap().to_vec())]);
+}
+
+#[test]
+fn midi_mode_keeps_the_master_apart_from_the_outputs_page() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Button { id: XctrlButtonType::FaderBank as u8 + 1, pressed: true }));
+    let bus = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 0, level: 0x4000 }));
+    let master = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 8, level: 0x4000 }));
+    assert_eq!(bus, vec![BridgeOutput::Vban(MidiPacket::new([0xe8, 0x00, 0x40], 2).pack().unwrap().to_vec())]);
+    assert_eq!(master, vec![BridgeOutput::Vban(MidiPacket::new([MASTER_FADER_STATUS, MASTER_FADER_CONTROL, 0x40], 3).pack().unwrap().to_vec())]);
+}
+
+#[test]
+fn text_mode_toggles_flags() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Text)).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Button { id: XctrlButtonType::Mute as u8, pressed: true }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Strip[0].Mute = 0;", 1).as_bytes())]);
+
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Button { id: XctrlButtonType::Mute as u8 + 1, pressed: true }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Strip[1].Mute = 1;", 2).as_bytes())]);
+}
+
+#[test]
+fn text_mode_sends_fader_gain() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Text)).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 8, level: 32767 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Bus[0].Gain = 12.0;", 1).as_bytes())]);
+}
+
+#[test]
+fn configured_pages_mix_strips_and_buses() {
+    let mut config = common::config(&[XTOUCH], OutputMode::Text);
+    config.pages = vec![PageConfig { name: "Mix".to_string(), faders: vec!["strip 1".to_string(), "bus 1".to_string()], colors: vec!["yellow".to_string()], ..PageConfig::default() }];
+    let mut bridge = Bridge::new(&config).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+
+    assert!(contains(&sent, &XctrlDisplay::new(0, XctrlDisplayColor::Yellow, b"Mic\0\0\0\0", &[0; 7]).as_bytes()));
+    assert!(contains(&sent, &XctrlDisplay::new(1, XctrlDisplayColor::Blue, b"Main\0\0\0", &[0; 7]).as_bytes()));
+    assert!(contains(&sent, &XctrlDisplay::new(2, XctrlDisplayColor::Off, &[0; 7], &[0; 7]).as_bytes()));
+
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 1, level: 32767 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Bus[0].Gain = 12.0;", 1).as_bytes())]);
+    assert!(bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 2, level: 32767 })).is_empty());
+}
+
+#[test]
+fn configured_button_rows_and_fader_range() {
+    let mut config = common::config(&[XTOUCH], OutputMode::Text);
+    config.buttons.mute = ButtonFunction::Solo;
+    config.faders = FaderRange { min: -40.0, max: 0.0, curve: FaderCurve::Linear };
+    let mut bridge = Bridge::new(&config).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(-40.0))));
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+
+    // Strip 0 is muted but not soloed
+    assert!(contains(&sent, &XctrlButton { id: XctrlButtonType::Mute as u8, state: 0 }.as_bytes()));
+    assert!(contains(&sent, &XctrlFader { id: 0, level: 0 }.as_bytes()));
+
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Button { id: XctrlButtonType::Mute as u8, pressed: true }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Strip[0].Solo = 1;", 1).as_bytes())]);
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 0, level: 32767 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Strip[0].Gain = 0.0;", 2).as_bytes())]);
+}
+
+#[test]
+fn reload_redraws_the_current_page() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Text)).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+    bridge.refresh();
+
+    let mut config = common::config(&[XTOUCH], OutputMode::Text);
+    config.pages = vec![PageConfig { name: "Mic".to_string(), faders: vec!["strip 1".to_string()], colors: vec!["red".to_string()], ..PageConfig::default() }];
+    assert_eq!(bridge.handle(BridgeEvent::Reload(Box::new(config))), vec![BridgeOutput::Status(BridgeStatus::ConfigReloaded)]);
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+
+    // No new RT packet is needed, the last one is redrawn onto the new page
+    assert!(contains(&sent, &XctrlDisplay::new(0, XctrlDisplayColor::Red, b"Mic\0\0\0\0", &[0; 7]).as_bytes()));
+    assert!(contains(&sent, &XctrlFader { id: 1, level: 0 }.as_bytes()));
+    assert!(bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 1, level: 32767 })).is_empty());
+}
+
+#[test]
+fn reload_keeps_the_last_good_config() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Text)).unwrap();
+    let mut config = common::config(&[XTOUCH], OutputMode::Text);
+    config.pages = vec![PageConfig { name: "Broken".to_string(), faders: vec!["strip 9".to_string()], ..PageConfig::default() }];
+
+    assert!(bridge.reload(&config).is_err());
+    assert_eq!(bridge.pages, Config::default().pages().unwrap());
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 1, level: 32767 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Strip[1].Gain = 12.0;", 1).as_bytes())]);
+}
+
+#[test]
+fn reload_keeps_comparing_against_the_addresses_in_use() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    let mut moved = common::config(&[EXTENDER], OutputMode::Text);
+    moved.faders.min = -40.0;
+    assert!(bridge.needs_restart(&moved));
+    bridge.reload(&moved).unwrap();
+
+    // The new fader range and output mode apply, the new surface waits for a restart
+    assert_eq!(bridge.fader_range.min, -40.0);
+    assert_eq!(bridge.output_mode, OutputMode::Text);
+    assert_eq!(bridge.devices[0].address, with_port(XTOUCH, 10111));
+    assert!(bridge.needs_restart(&moved));
+    assert!(!bridge.needs_restart(&common::config(&[XTOUCH], OutputMode::Text)));
+}
+
+#[test]
+fn refuses_to_start_with_broken_pages() {
+    let mut config = common::config(&[XTOUCH], OutputMode::Text);
+    config.pages = vec![PageConfig { name: "Broken".to_string(), faders: vec!["strip 9".to_string()], ..PageConfig::default() }];
+    assert!(Bridge::new(&config).is_err());
+}
+
+#[test]
+fn reload_reports_how_it_went() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Text)).unwrap();
+    let mut broken = common::config(&[XTOUCH], OutputMode::Text);
+    broken.pages = vec![PageConfig { name: "Broken".to_string(), faders: vec!["strip 9".to_string()], ..PageConfig::default() }];
+    match bridge.handle(BridgeEvent::Reload(Box::new(broken))).as_slice() {
+        [BridgeOutput::Status(BridgeStatus::ConfigRefused(_))] => {},
+        out => panic!("expected the reload to be refused, got {:?}", out)
+    }
+
+    let moved = common::config(&[EXTENDER], OutputMode::Text);
+    assert_eq!(
+        bridge.handle(BridgeEvent::Reload(Box::new(moved))),
+        vec![BridgeOutput::Status(BridgeStatus::ConfigReloaded), BridgeOutput::Status(BridgeStatus::RestartNeeded)]
+    );
+}
+
+fn three_pages() -> Config {
+    let mut config = common::config(&[XTOUCH], OutputMode::Text);
+    let page = |name: &str, fader: &str, button: Option<PageButton>| PageConfig { name: name.to_string(), faders: vec![fader.to_string()], button, ..PageConfig::default() };
+    config.pages = vec![page("Mic", "strip 1", None), page("Main", "bus 1", Some(PageButton::Outputs)), page("Aux", "bus 2", Some(PageButton::Aux))];
+    config
+}
+
+fn press(bridge: &mut Bridge, id: u8) {
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Button { id, pressed: true }));
+}
+
+#[test]
+fn fader_bank_steps_through_every_page() {
+    let mut bridge = Bridge::new(&three_pages()).unwrap();
+    let fader_bank = XctrlButtonType::FaderBank as u8;
+
+    press(&mut bridge, fader_bank + 1);
+    press(&mut bridge, fader_bank + 1);
+    assert_eq!(bridge.position, 16);
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 0, level: 32767 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Bus[1].Gain = 12.0;", 3).as_bytes())]);
+
+    // Nothing past the last page, and no further right to go
+    press(&mut bridge, fader_bank + 1);
+    assert_eq!(bridge.position, 16);
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+    assert!(contains(&sent, &XctrlButton { id: fader_bank, state: 127 }.as_bytes()));
+    assert!(contains(&sent, &XctrlButton { id: fader_bank + 1, state: 0 }.as_bytes()));
+
+    press(&mut bridge, fader_bank);
+    assert_eq!(bridge.position, 8);
+}
+
+#[test]
+fn channel_bank_shifts_one_fader() {
+    let mut bridge = Bridge::new(&three_pages()).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+    let channel_bank = XctrlButtonType::ChannelBank as u8;
+
+    press(&mut bridge, channel_bank + 1);
+    assert_eq!(bridge.position, 1);
+    // The last fader now shows the first one of the next page
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+    assert!(contains(&sent, &XctrlDisplay::new(7, XctrlDisplayColor::Blue, b"Main\0\0\0", &[0; 7]).as_bytes()));
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 7, level: 32767 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Bus[0].Gain = 12.0;", 2).as_bytes())]);
+
+    // Fader Bank lines the surface back up with a page
+    press(&mut bridge, XctrlButtonType::FaderBank as u8);
+    assert_eq!(bridge.position, 0);
+    press(&mut bridge, channel_bank);
+    assert_eq!(bridge.position, 0);
+}
+
+#[test]
+fn page_buttons_jump_to_their_pages() {
+    let mut bridge = Bridge::new(&three_pages()).unwrap();
+    press(&mut bridge, XctrlButtonType::Aux as u8);
+    assert_eq!(bridge.position, 16);
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+    assert!(contains(&sent, &XctrlButton { id: XctrlButtonType::Aux as u8, state: 127 }.as_bytes()));
+    assert!(contains(&sent, &XctrlButton { id: XctrlButtonType::Outputs as u8, state: 0 }.as_bytes()));
+
+    press(&mut bridge, XctrlButtonType::Outputs as u8);
+    assert_eq!(bridge.position, 8);
+    // No page has the Inputs button here
+    press(&mut bridge, XctrlButtonType::Inputs as u8);
+    assert_eq!(bridge.position, 8);
+}
+
+#[test]
+fn gain_encoders_start_from_the_send_level() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Text)).unwrap();
+    let mut packet = rt_packet(-10.0);
+    packet.set_input_gain_layer(1, 0, -20.0);
+    bridge.handle(BridgeEvent::Vban(Box::new(packet)));
+    press(&mut bridge, XctrlButtonType::Track as u8);
+
+    // Selecting bus 2 puts its sends on the strips, and the first detent moves on from the send level
+    press(&mut bridge, XctrlButtonType::FaderBank as u8 + 1);
+    press(&mut bridge, XctrlButtonType::Select as u8 + 1);
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Encoder { id: 0, delta: 1 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Strip[0].GainLayer[1] = -19.5;", 4).as_bytes())]);
+
+    // And back on the normal pages from the strip gain
+    press(&mut bridge, XctrlButtonType::FaderBank as u8);
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Encoder { id: 0, delta: 1 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Strip[0].Gain = -9.5;", 6).as_bytes())]);
+}
+
+#[test]
+fn sends_on_fader_needs_gain_layers() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Text)).unwrap();
+    press(&mut bridge, XctrlButtonType::FaderBank as u8 + 1);
+    let select_bus = XctrlButtonType::Select as u8 + 1;
+
+    // VoiceMeeter Standard has no gain layers, so the faders stay on the buses
+    let mut standard = rt_packet(0.0);
+    standard.voicemeeter_type = VoiceMeeterType::Standard as u8;
+    bridge.handle(BridgeEvent::Vban(Box::new(standard)));
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Button { id: select_bus, pressed: true }));
+    assert!(out.contains(&BridgeOutput::Status(BridgeStatus::SendsOnFaderNeedsGainLayers)));
+    assert_eq!(bridge.send_layer, None);
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 0, level: 32767 }));
+    assert_eq!(out, vec![BridgeOutput::Vban(TextPacket::new("Bus[0].Gain = 12.0;", 3).as_bytes())]);
+
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+    press(&mut bridge, select_bus);
+    assert_eq!(bridge.send_layer, Some(1));
+
+    // And an edition change takes the layer away again
+    let mut standard = rt_packet(0.0);
+    standard.voicemeeter_type = VoiceMeeterType::Standard as u8;
+    bridge.handle(BridgeEvent::Vban(Box::new(standard)));
+    assert_eq!(bridge.send_layer, None);
+}
+
+fn fader_messages(sent: &[u8], id: u8) -> Vec<Vec<u8>> {
+    sent.windows(5)
+        .filter(|window| window[0] == 0xf0 && window[1] == 0xe0 + id && window[4] == 0xf7)
+        .map(|window| window.to_vec())
+        .collect()
+}
+
+#[test]
+fn touched_faders_wait_for_voicemeeter_after_release() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+    bridge.refresh();
+
+    let moved_to = FaderRange::default().level(-6.0);
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::FaderTouch { id: 0, touched: true }));
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 0, level: moved_to }));
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(-10.0))));
+    assert!(fader_messages(&xctrl_bytes(&bridge.refresh(), XTOUCH), 0).is_empty());
+
+    // Let go, the RT packet from before still has the old level so nothing moves yet
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::FaderTouch { id: 0, touched: false }));
+    assert!(fader_messages(&xctrl_bytes(&bridge.refresh(), XTOUCH), 0).is_empty());
+
+    // VoiceMeeter settled somewhere other than where the fader was left
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(-3.0))));
+    let correction = XctrlFader { id: 0, level: FaderRange::default().level(-3.0) };
+    assert_eq!(fader_messages(&xctrl_bytes(&bridge.refresh(), XTOUCH), 0), vec![correction.as_bytes().to_vec()]);
+    assert!(fader_messages(&xctrl_bytes(&bridge.refresh(), XTOUCH), 0).is_empty());
+}
+
+#[test]
+fn released_faders_stay_put_when_voicemeeter_agrees() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(0.0))));
+    bridge.refresh();
+
+    let moved_to = FaderRange::default().level(-6.0);
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::FaderTouch { id: 0, touched: true }));
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 0, level: moved_to }));
+    bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::FaderTouch { id: 0, touched: false }));
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(-6.0))));
+    assert!(fader_messages(&xctrl_bytes(&bridge.refresh(), XTOUCH), 0).is_empty());
+}
+
+#[test]
+fn midi_mode_follows_the_assignment_buttons() {
+    let mut bridge = Bridge::new(&common::config(&[XTOUCH], OutputMode::Midi)).unwrap();
+    bridge.handle(BridgeEvent::Vban(Box::new(rt_packet(-10.0))));
+    bridge.refresh();
+
+    press(&mut bridge, XctrlButtonType::Eq as u8);
+    assert_eq!(bridge.encoders[0].assignment, EncoderAssignment::EqBass);
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+    assert!(contains(&sent, &XctrlButton { id: XctrlButtonType::Eq as u8, state: 127 }.as_bytes()));
+    assert!(contains(&sent, &XctrlButton { id: XctrlButtonType::Pan as u8, state: 0 }.as_bytes()));
+    assert!(contains(&sent, &XctrlEncoderRing::new(0, XctrlEncoderRingMode::BoostCut, 0.5).as_bytes()));
+
+    // The turn goes to VoiceMeeter on the EQ bass controls and moves the ring along with it
+    let out = bridge.handle(BridgeEvent::Xctrl(0, XctrlMessage::Encoder { id: 1, delta: 6 }));
+    match out.as_slice() {
+        [BridgeOutput::Vban(data)] => assert_eq!(&data[28..30], &[0xb0, EncoderAssignment::EqBass.midi_control() + 1]),
+        _ => panic!("expected one MIDI message, got {:?}", out)
+    }
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+    assert!(contains(&sent, &XctrlEncoderRing::new(1, XctrlEncoderRingMode::BoostCut, 15.0 / 24.0).as_bytes()));
+
+    // Track shows the strip gains from the RT packet
+    press(&mut bridge, XctrlButtonType::Track as u8);
+    let sent = xctrl_bytes(&bridge.refresh(), XTOUCH);
+    assert!(contains(&sent, &XctrlEncoderRing::new(0, XctrlEncoderRingMode::Wrap, 50.0 / 72.0).as_bytes()));
+}
@@ -0,0 +1,51 @@
+// Each test binary uses its own share of these helpers
+#![allow(dead_code)]
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use vban_xctrl::*;
+
+// A Banana RT packet with every strip at the given gain and strip 0 muted and labelled "Mic"
+pub fn rt_packet_bytes(gain: f32) -> [u8; 1412] {
+    let mut buf = [0u8; 1412];
+    buf[0..4].copy_from_slice(b"VBAN");
+    buf[4] = VBANProtocol::Service as u8;
+    buf[8..24].copy_from_slice(b"Voicemeeter-RTP\0");
+    buf[28] = VoiceMeeterType::Banana as u8;
+    for i in 0..8 {
+        let raw = ((gain * 100.0) as i16).to_le_bytes();
+        buf[308 + (i * 2)..310 + (i * 2)].copy_from_slice(&raw);
+        buf[436 + (i * 2)..438 + (i * 2)].copy_from_slice(&raw);
+    }
+    buf[244..248].copy_from_slice(&STATE_MUTE.to_le_bytes());
+    buf[452..455].copy_from_slice(b"Mic");
+    buf[932..936].copy_from_slice(b"Main");
+    buf
+}
+
+pub fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+// The command line shorthand, with surfaces given as IPs
+pub fn config(surfaces: &[&str], output_mode: OutputMode) -> Config {
+    let mut config = Config::default();
+    config.xctrl.surfaces = surfaces.iter().map(|surface| surface.to_string()).collect();
+    config.vban.host = "10.0.0.3".to_string();
+    config.vban.output = output_mode;
+    config
+}
+
+// Somewhere for a Capture to write to that the test can still read
+pub struct SharedBuffer(pub Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+extern crate vban_xctrl;
+
+use vban_xctrl::*;
+
+#[test]
+fn example_config_loads() {
+    let config = Config::load("config.example.toml").unwrap();
+    assert_eq!(config.xctrl_bind(), "192.168.1.10:10111");
+    assert_eq!(config.surface_addresses(), vec!["192.168.1.20:10111".to_string()]);
+    assert_eq!(config.vban_address(), "192.168.1.30:6980");
+    assert_eq!(config.vban.output, OutputMode::Text);
+    assert_eq!(config.faders, FaderRange::default());
+
+    let pages = config.pages().unwrap();
+    assert_eq!(pages.len(), 3);
+    assert_eq!(pages[0].colors[0], XctrlDisplayColor::Red);
+    assert_eq!(pages[1].channels[7], Some(VoiceMeeterChannel::Bus(7)));
+    assert_eq!(pages[1].colors[7], XctrlDisplayColor::Blue);
+    assert_eq!(pages[2].channels[6], Some(VoiceMeeterChannel::Bus(0)));
+    assert_eq!(pages[2].button, Some(PageButton::Aux));
+}
+
+#[test]
+fn missing_sections_take_defaults() {
+    let config = Config::parse("[xctrl]\nsurfaces = [\"10.0.0.2\", \"10.0.0.4:9000\"]\n[vban]\nhost = \"10.0.0.3\"\n").unwrap();
+    assert_eq!(config.surface_addresses(), vec!["10.0.0.2:10111".to_string(), "10.0.0.4:9000".to_string()]);
+    assert_eq!(config.vban_bind(), "0.0.0.0:6980");
+    assert_eq!(config.master_fader(), MasterFader::Bus(0));
+    assert_eq!(config.buttons, ButtonRows::default());
+    assert_eq!(config.pages().unwrap(), vec![Page::strips("Inputs").with_button(PageButton::Inputs), Page::buses("Outputs").with_button(PageButton::Outputs)]);
+}
+
+#[test]
+fn command_line_shorthand_still_works() {
+    let args: Vec<String> = ["192.168.1.10", "192.168.1.20,192.168.1.21", "192.168.1.30", "text", "selected"].iter().map(|arg| arg.to_string()).collect();
+    let config = Config::from_args(&args).unwrap();
+    assert_eq!(config.surface_addresses(), vec!["192.168.1.20:10111".to_string(), "192.168.1.21:10111".to_string()]);
+    assert_eq!(config.vban_address(), "192.168.1.30:6980");
+    assert_eq!(config.vban.output, OutputMode::Text);
+    assert_eq!(config.master_fader(), MasterFader::Selected);
+
+    assert!(Config::from_args(&args[0..2]).is_err());
+}
+
+#[test]
+fn ports_are_only_added_where_missing() {
+    let config = Config::parse("bind = \"::\"\n[xctrl]\nsurfaces = [\"fd00::20\", \"[fd00::21]:9000\", \"10.0.0.4\"]\n[vban]\nhost = \"fd00::30\"\n").unwrap();
+    assert_eq!(config.surface_addresses(), vec!["[fd00::20]:10111".to_string(), "[fd00::21]:9000".to_string(), "10.0.0.4:10111".to_string()]);
+    assert_eq!(config.xctrl_bind(), "[::]:10111");
+    assert_eq!(config.vban_address(), "[fd00::30]:6980");
+    assert_eq!(with_port("voicemeeter.local", 6980), "voicemeeter.local:6980");
+    assert_eq!(with_port("voicemeeter.local:7000", 6980), "voicemeeter.local:7000");
+}
+
+#[test]
+fn mixed_pages() {
+    let config = Config::parse("[xctrl]\nsurfaces = [\"10.0.0.2\"]\n[vban]\nhost = \"10.0.0.3\"\n[[pages]]\nname = \"Mix\"\nfaders = [\"strip 1\", \"none\", \"bus 3\"]\ncolors = [\"white-inverted\"]\n").unwrap();
+    let pages = config.pages().unwrap();
+    assert_eq!(pages.len(), 1);
+    assert_eq!(pages[0].channels[0..4], [Some(VoiceMeeterChannel::Strip(0)), None, Some(VoiceMeeterChannel::Bus(2)), None]);
+    assert_eq!(pages[0].colors[0..3], [XctrlDisplayColor::WhiteInv, XctrlDisplayColor::Off, XctrlDisplayColor::Blue]);
+}
+
+#[test]
+fn rejects_bad_configs() {
+    let base = "[xctrl]\nsurfaces = [\"10.0.0.2\"]\n[vban]\nhost = \"10.0.0.3\"\n";
+    assert!(Config::parse(base).is_ok());
+    assert!(Config::parse("[vban]\nhost = \"10.0.0.3\"\n").is_err());
+    assert!(Config::parse("[xctrl]\nsurfaces = [\"xtouch.local\"]\n[vban]\nhost = \"10.0.0.3\"\n").is_err());
+    assert!(Config::parse("[xctrl]\nsurfaces = [\"xtouch.local:10111\"]\n[vban]\nhost = \"10.0.0.3\"\n").is_err());
+    assert!(Config::parse(&format!("{}master = \"9\"\n", base)).is_err());
+    assert!(Config::parse(&format!("{}output = \"osc\"\n", base)).is_err());
+    assert!(Config::parse(&format!("{}[faders]\nmin = 10.0\nmax = 0.0\n", base)).is_err());
+    assert!(Config::parse(&format!("{}[[pages]]\nfaders = [\"strip 9\"]\n", base)).is_err());
+    assert!(Config::parse(&format!("{}[[pages]]\nfaders = [\"strip 1\"]\ncolors = [\"purple\"]\n", base)).is_err());
+    assert!(Config::parse(&format!("{}[buttons]\nrec = \"record\"\n", base)).is_err());
+    assert!(Config::parse(&format!("{}[faders]\ncurve = \"steep\"\n", base)).is_err());
+    assert!(Config::parse(&format!("{}typo = 1\n", base)).is_err());
+    assert!(Config::parse(&format!("{}[[pages]]\nbutton = \"user\"\n", base)).is_err());
+    assert!(Config::parse(&format!("{}[[pages]]\nbutton = \"aux\"\n[[pages]]\nbutton = \"aux\"\n", base)).is_err());
+}
@@ -0,0 +1,64 @@
+extern crate packed_struct;
+extern crate vban_xctrl;
+
+use std::net::SocketAddr;
+
+use packed_struct::prelude::*;
+use vban_xctrl::*;
+
+fn host(host_name: &str, application: &str, device_type: u32) -> VbanHost {
+    let identity = VBANPing0::new(device_type, 0, [3, 1, 0, 4]).with_names(application, "VB-Audio", application, host_name);
+    let ping = PingPacket::parse(&ping_reply(&identity, 7)).unwrap();
+    VbanHost::from_ping("192.168.1.30:6980".parse::<SocketAddr>().unwrap(), &ping)
+}
+
+#[test]
+fn pings_carry_the_host_details() {
+    let identity = VBANPing0::new(PING_TYPE_VIRTUAL_MIXER, PING_FEATURE_TXT, [3, 1, 0, 4]).with_names("Potato", "VB-Audio", "Voicemeeter Potato", "STUDIO-PC");
+    let request = ping_request(&identity, 2);
+    assert_eq!(request.len(), PingPacket::LENGTH);
+    assert!(PingPacket::is_ping(&request));
+
+    let ping = PingPacket::parse(&request).unwrap();
+    assert!(!ping.is_reply());
+    assert_eq!(ping.header.frame_id, 2);
+    assert_eq!(ping.body, identity);
+    assert_eq!(ping.body.host_name(), "STUDIO-PC");
+    assert_eq!(ping.body.version(), [3, 1, 0, 4]);
+    assert!(PingPacket::parse(&ping_reply(&identity, 3)).unwrap().is_reply());
+
+    // Other service packets and short ones aren't pings
+    assert!(!PingPacket::is_ping(&RTPacket::new(VoiceMeeterType::Banana, "Voicemeeter-RTP", 1).pack().unwrap()));
+    assert_eq!(PingPacket::parse(&request[..100]), Err(VbanError::TooShort { expected: 704, got: 100 }));
+}
+
+#[test]
+fn selects_voicemeeter_by_host_or_stream_name() {
+    let studio = host("STUDIO-PC", "Voicemeeter Banana", 0);
+    assert!(studio.is_voicemeeter());
+    assert_eq!(studio.stream, "VBAN Service");
+    assert!(HostSelector::new("").matches(&studio));
+    assert!(HostSelector::new("studio-pc").matches(&studio));
+    assert!(HostSelector::new("VBAN Service").matches(&studio));
+    assert!(!HostSelector::new("LAPTOP").matches(&studio));
+
+    // Anything that isn't VoiceMeeter is never picked, another bridge included
+    let bridge = host("STUDIO-PC", "vban_xctrl", PING_TYPE_RECEPTOR);
+    assert!(!bridge.is_voicemeeter());
+    assert!(!HostSelector::new("STUDIO-PC").matches(&bridge));
+    assert!(host("MIXER", "Matrix", PING_TYPE_VIRTUAL_MIXER).is_voicemeeter());
+
+    // The first VoiceMeeter found is kept to afterwards
+    assert_eq!(HostSelector::new("").pinned(&studio), HostSelector::new("STUDIO-PC"));
+    assert_eq!(HostSelector::new("LAPTOP").pinned(&studio), HostSelector::new("LAPTOP"));
+}
+
+#[test]
+fn auto_host_turns_on_discovery() {
+    let config = Config::parse("[xctrl]\nsurfaces = [\"10.0.0.2\"]\n[vban]\nhost = \"auto\"\ndiscover = \"STUDIO-PC\"\nbroadcast = \"192.168.1.255\"\n").unwrap();
+    assert_eq!(Discovery::from_config(&config), Some(Discovery { selector: HostSelector::new("STUDIO-PC"), broadcast: "192.168.1.255:6980".to_string() }));
+
+    let mut given = config.clone();
+    given.vban.host = "192.168.1.30".to_string();
+    assert_eq!(Discovery::from_config(&given), None);
+}
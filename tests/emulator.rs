@@ -0,0 +1,96 @@
+#![cfg(feature = "emulator")]
+
+extern crate vban_xctrl;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use vban_xctrl::*;
+
+mod common;
+
+use common::rt_packet_bytes;
+
+#[test]
+fn shows_what_the_bridge_sends() {
+    let mut state = XctrlState::blank();
+    state.displays[2] = XctrlDisplay::new(2, XctrlDisplayColor::CyanInv, b"Mic\0\0\0\0", b"Left\0\0\0");
+    state.meters[3].level = 12;
+    state.faders[8].level = 24000;
+    state.rings[4] = XctrlEncoderRing::new(4, XctrlEncoderRingMode::BoostCut, 0.3);
+    state.buttons[XctrlButtonType::Mute as usize + 1].state = 127;
+    state.buttons[XctrlButtonType::Flip as usize].state = 1;
+
+    let mut emulator = XtouchEmulator::new();
+    for datagram in state.changes(None) {
+        emulator.apply(&datagram);
+    }
+    assert!(emulator.surface == state);
+    assert!(emulator.button_lit(XctrlButtonType::Mute as u8 + 1));
+    assert_eq!(emulator.unknown, 0);
+
+    emulator.apply(&XCTRL_HANDSHAKE_REPLY);
+    assert!(emulator.connected);
+    emulator.apply(&[0xf0, 0x12, 0xf7, 0x90]);
+    assert_eq!(emulator.unknown, 2);
+}
+
+#[test]
+fn end_bytes_inside_messages_dont_split_them() {
+    let mut state = XctrlState::blank();
+    state.faders[1].level = 0x01f7;
+    state.displays[0] = XctrlDisplay::new(0, XctrlDisplayColor::Red, b"\xf7\0\0\0\0\0\0", b"\0\0\0\0\0\0\xf7");
+
+    let mut emulator = XtouchEmulator::new();
+    for datagram in state.changes(None) {
+        emulator.apply(&datagram);
+    }
+    assert_eq!(emulator.unknown, 0);
+    assert_eq!(emulator.fader_level(1), 0x01f7);
+    assert!(emulator.surface == state);
+}
+
+#[test]
+fn controls_send_surface_messages() {
+    let mut emulator = XtouchEmulator::new();
+    let fader = emulator.nudge_fader(2, 1000);
+    assert_eq!(XctrlMessage::parse(&fader), vec![
+        XctrlMessage::FaderTouch { id: 2, touched: true },
+        XctrlMessage::Fader { id: 2, level: 1000 },
+        XctrlMessage::FaderTouch { id: 2, touched: false }
+    ]);
+    // Faders stop at the ends of their travel
+    emulator.nudge_fader(2, -5000);
+    assert_eq!(emulator.fader_level(2), 0);
+
+    assert_eq!(XctrlMessage::parse(&emulator.press_strip(XctrlButtonType::Solo, 5)), vec![
+        XctrlMessage::Button { id: XctrlButtonType::Solo as u8 + 5, pressed: true },
+        XctrlMessage::Button { id: XctrlButtonType::Solo as u8 + 5, pressed: false }
+    ]);
+    assert_eq!(XctrlMessage::parse(&emulator.turn_encoder(1, -2)), vec![XctrlMessage::Encoder { id: 1, delta: -2 }]);
+    assert_eq!(XctrlMessage::parse(&emulator.probe()), vec![XctrlMessage::Probe]);
+}
+
+#[test]
+fn connects_to_the_bridge() {
+    let address = |address: &str| address.parse::<SocketAddr>().unwrap();
+    let (xctrl, xtouch) = MemoryTransport::pair(address("10.0.0.1:10111"), address("10.0.0.2:10111"));
+    let (vban, voicemeeter) = MemoryTransport::pair(address("10.0.0.1:6980"), address("10.0.0.3:6980"));
+    start(Bridge::new(&common::config(&["10.0.0.2"], OutputMode::Midi)).unwrap(), xctrl, vban, "10.0.0.3:6980".to_string());
+
+    let mut emulator = XtouchEmulator::new();
+    xtouch.send_to(&emulator.probe(), "10.0.0.1:10111").unwrap();
+    voicemeeter.send_to(&rt_packet_bytes(0.0), "10.0.0.1:6980").unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let filled_in = |emulator: &XtouchEmulator| {
+        emulator.connected && emulator.surface.displays[0].top_text == *b"Mic\0\0\0\0" && emulator.button_lit(XctrlButtonType::Mute as u8)
+    };
+    while !filled_in(&emulator) {
+        assert!(Instant::now() < deadline, "the surface never filled in");
+        let mut buf = [0; 2048];
+        if let Ok((amt, _)) = xtouch.recv_timeout(&mut buf, Duration::from_millis(100)) {
+            emulator.apply(&buf[..amt]);
+        }
+    }
+}
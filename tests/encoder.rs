@@ -0,0 +1,112 @@
+extern crate vban_xctrl;
+
+use vban_xctrl::*;
+
+const ASSIGNMENTS: [EncoderAssignment; 7] = [
+    EncoderAssignment::Gain,
+    EncoderAssignment::Pan,
+    EncoderAssignment::EqBass,
+    EncoderAssignment::EqMid,
+    EncoderAssignment::EqTreble,
+    EncoderAssignment::Reverb,
+    EncoderAssignment::Delay
+];
+
+fn script(assignment: EncoderAssignment, value: f32) -> String {
+    VoiceMeeterCommand::new(VoiceMeeterChannel::Strip(2), assignment.parameter(value)).as_script()
+}
+
+#[test]
+fn each_assignment_has_its_own_script() {
+    assert_eq!(script(EncoderAssignment::Gain, -6.5), "Strip[2].Gain = -6.5;");
+    assert_eq!(script(EncoderAssignment::Pan, -0.25), "Strip[2].Pan_x = -0.25;");
+    assert_eq!(script(EncoderAssignment::EqBass, 3.0), "Strip[2].EQGain1 = 3.0;");
+    assert_eq!(script(EncoderAssignment::EqMid, -1.5), "Strip[2].EQGain2 = -1.5;");
+    assert_eq!(script(EncoderAssignment::EqTreble, 12.0), "Strip[2].EQGain3 = 12.0;");
+    assert_eq!(script(EncoderAssignment::Reverb, 4.5), "Strip[2].Reverb = 4.5;");
+    assert_eq!(script(EncoderAssignment::Delay, 10.0), "Strip[2].Delay = 10.0;");
+}
+
+#[test]
+fn each_assignment_has_its_own_ring() {
+    let ring = |assignment: EncoderAssignment, value: f32| {
+        let mut encoders = EncoderState::new();
+        encoders.assignment = assignment;
+        encoders.set_value(assignment, 0, value);
+        encoders.ring(0)
+    };
+
+    // Gain wraps round from the bottom of its range, unity a little past halfway
+    assert_eq!(ring(EncoderAssignment::Gain, -60.0).as_bytes(), XctrlEncoderRing::new(0, XctrlEncoderRingMode::Wrap, 0.0).as_bytes());
+    assert_eq!(ring(EncoderAssignment::Gain, 0.0).value, 9);
+    assert_eq!(ring(EncoderAssignment::Gain, 0.0).mode, XctrlEncoderRingMode::Wrap as u8);
+    // Pan is a single dot, centered for the middle
+    assert_eq!(ring(EncoderAssignment::Pan, 0.0).as_bytes(), XctrlEncoderRing::new(0, XctrlEncoderRingMode::SingleDot, 0.5).as_bytes());
+    assert_eq!(ring(EncoderAssignment::Pan, -0.5).value, 1);
+    // EQ boosts and cuts away from the middle
+    for assignment in [EncoderAssignment::EqBass, EncoderAssignment::EqMid, EncoderAssignment::EqTreble].iter() {
+        assert_eq!(ring(*assignment, 0.0).as_bytes(), XctrlEncoderRing::new(0, XctrlEncoderRingMode::BoostCut, 0.5).as_bytes());
+        assert_eq!(ring(*assignment, 12.0).value, 11);
+    }
+    // Sends fill up from nothing
+    for assignment in [EncoderAssignment::Reverb, EncoderAssignment::Delay].iter() {
+        assert_eq!(ring(*assignment, 0.0).as_bytes(), XctrlEncoderRing::new(0, XctrlEncoderRingMode::Wrap, 0.0).as_bytes());
+        assert_eq!(ring(*assignment, 5.0).value, 6);
+    }
+}
+
+#[test]
+fn assignment_buttons_step_through_their_options() {
+    let mut encoders = EncoderState::new();
+    assert_eq!(encoders.assignment, EncoderAssignment::Pan);
+    let presses = [
+        (XctrlButtonType::Eq, EncoderAssignment::EqBass),
+        (XctrlButtonType::Eq, EncoderAssignment::EqMid),
+        (XctrlButtonType::Eq, EncoderAssignment::EqTreble),
+        (XctrlButtonType::Eq, EncoderAssignment::EqBass),
+        (XctrlButtonType::Send, EncoderAssignment::Reverb),
+        (XctrlButtonType::Send, EncoderAssignment::Delay),
+        (XctrlButtonType::Send, EncoderAssignment::Reverb),
+        (XctrlButtonType::Track, EncoderAssignment::Gain),
+        (XctrlButtonType::Pan, EncoderAssignment::Pan)
+    ];
+    for (button, assignment) in presses.iter() {
+        assert!(encoders.select(*button as u8));
+        assert_eq!(encoders.assignment, *assignment);
+        assert_eq!(assignment.button() as u8, *button as u8);
+    }
+    assert!(!encoders.select(XctrlButtonType::Flip as u8));
+    assert_eq!(encoders.assignment, EncoderAssignment::Pan);
+}
+
+#[test]
+fn turns_stay_in_range_and_pushes_reset() {
+    for assignment in ASSIGNMENTS.iter() {
+        let mut encoders = EncoderState::new();
+        encoders.assignment = *assignment;
+        let (min, max, default) = assignment.range();
+        for _ in 0..4 {
+            encoders.turn(3, 63);
+        }
+        assert_eq!(encoders.value(*assignment, 3), max);
+        for _ in 0..4 {
+            encoders.turn(3, -63);
+        }
+        assert_eq!(encoders.value(*assignment, 3), min);
+        assert_eq!(encoders.turn(3, 1), min + assignment.step());
+        assert_eq!(encoders.reset(3), default);
+        // Other strips and assignments are left alone
+        assert_eq!(encoders.value(*assignment, 2), default);
+    }
+}
+
+#[test]
+fn midi_controls_for_each_assignment_do_not_overlap() {
+    for (i, assignment) in ASSIGNMENTS.iter().enumerate() {
+        for other in ASSIGNMENTS[i + 1..].iter() {
+            let (a, b) = (assignment.midi_control(), other.midi_control());
+            assert!(a + 8 <= b || b + 8 <= a, "{:?} and {:?} share controls", assignment, other);
+        }
+    }
+    assert_eq!(EncoderAssignment::Pan.midi_control(), 0x10);
+}
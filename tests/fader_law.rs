@@ -0,0 +1,82 @@
+extern crate vban_xctrl;
+
+use vban_xctrl::*;
+
+fn range(curve: FaderCurve) -> FaderRange {
+    FaderRange { curve, ..FaderRange::default() }
+}
+
+#[test]
+fn audio_curve_puts_unity_at_three_quarters() {
+    let law = range(FaderCurve::Audio);
+    assert_eq!(law.position(0.0), 0.75);
+    assert_eq!(law.gain_at(0.75), 0.0);
+    assert_eq!(law.position(-20.0), 0.375);
+    assert_eq!(law.position(6.0), 0.875);
+    assert_eq!(law.level(-60.0), 0);
+    assert_eq!(law.level(12.0), 32767);
+}
+
+#[test]
+fn every_curve_covers_the_range() {
+    for curve in [FaderCurve::Linear, FaderCurve::Audio, FaderCurve::Amplitude].iter() {
+        let law = range(*curve);
+        assert_eq!(law.gain(0), -60.0);
+        assert_eq!(law.gain(32767), 12.0);
+        assert_eq!(law.level(-100.0), 0);
+        assert_eq!(law.level(100.0), 32767);
+    }
+}
+
+#[test]
+fn levels_and_gains_agree_both_ways() {
+    for curve in [FaderCurve::Linear, FaderCurve::Audio, FaderCurve::Amplitude].iter() {
+        let law = range(*curve);
+        let mut last_gain = law.gain(0);
+        for level in (0..=32767).step_by(97) {
+            let gain = law.gain(level);
+            assert!(gain >= last_gain, "{:?} falls at level {}", curve, level);
+            last_gain = gain;
+
+            let back = law.level(gain) as i32;
+            assert!((back - level as i32).abs() <= 1, "{:?} level {} came back as {}", curve, level, back);
+        }
+    }
+}
+
+#[test]
+fn every_curve_stretches_to_a_narrower_range() {
+    for curve in [FaderCurve::Linear, FaderCurve::Audio, FaderCurve::Amplitude].iter() {
+        let law = FaderRange { min: -40.0, max: 0.0, curve: *curve };
+        assert_eq!(law.level(-40.0), 0);
+        assert_eq!(law.level(0.0), 32767);
+        for level in (0..=32767).step_by(97) {
+            let back = law.level(law.gain(level)) as i32;
+            assert!((back - level as i32).abs() <= 1, "{:?} level {} came back as {}", curve, level, back);
+        }
+    }
+
+    // Without a boost there's no unity mark to keep, so the whole audio curve stretches instead
+    let law = FaderRange { min: -40.0, max: 0.0, curve: FaderCurve::Audio };
+    assert!((law.gain_at(0.75) - -6.6667).abs() < 0.001);
+}
+
+#[test]
+fn audio_curve_keeps_unity_on_the_mark_over_any_range() {
+    let law = FaderRange { min: -80.0, max: 6.0, curve: FaderCurve::Audio };
+    assert_eq!(law.position(0.0), 0.75);
+    assert_eq!(law.gain_at(0.75), 0.0);
+    assert_eq!(law.level(-80.0), 0);
+    assert_eq!(law.level(6.0), 32767);
+
+    // Cuts scale by 80 / 60 and boosts by 6 / 12, each side keeping its share of the travel
+    assert!((law.gain_at(0.375) - -26.6667).abs() < 0.001);
+    assert!((law.gain_at(0.875) - 3.0).abs() < 0.0001);
+    assert!((law.position(-16.0) - 0.5).abs() < 0.0001);
+    assert!((law.position(4.5) - 0.9375).abs() < 0.0001);
+
+    for level in (0..=32767).step_by(97) {
+        let back = law.level(law.gain(level)) as i32;
+        assert!((back - level as i32).abs() <= 1, "level {} came back as {}", level, back);
+    }
+}
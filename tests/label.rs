@@ -0,0 +1,63 @@
+extern crate vban_xctrl;
+
+use vban_xctrl::*;
+
+// Both lines as text, without the NUL padding
+fn lines(label: &str) -> (String, String) {
+    let (top, bottom) = format_label(label);
+    let text = |line: [u8; 7]| String::from_utf8(line.to_vec()).unwrap().trim_end_matches('\0').to_string();
+    (text(top), text(bottom))
+}
+
+fn lines_of(top: &str, bottom: &str) -> (String, String) {
+    (top.to_string(), bottom.to_string())
+}
+
+#[test]
+fn short_labels_take_the_top_line() {
+    assert_eq!(lines("Mic"), lines_of("Mic", ""));
+    assert_eq!(lines("  Mic \0\0\0\0"), lines_of("Mic", ""));
+    assert_eq!(lines("\0\0\0"), lines_of("", ""));
+    assert_eq!(format_label(""), ([0; 7], [0; 7]));
+}
+
+#[test]
+fn splits_between_words() {
+    assert_eq!(lines("Desktop Audio"), lines_of("Desktop", "Audio"));
+    assert_eq!(lines("Music Player"), lines_of("Music", "Player"));
+    assert_eq!(lines("A1 Main Out"), lines_of("A1 Main", "Out"));
+    assert_eq!(lines("Headphones"), lines_of("Headpho", "nes"));
+}
+
+#[test]
+fn abbreviates_what_does_not_fit() {
+    assert_eq!(lines("Microphone 1"), lines_of("Micrphn", "1"));
+    assert_eq!(lines("Desktop Audio Left"), lines_of("Desktop", "Aud Lft"));
+    assert_eq!(lines("Extraordinarily"), lines_of("Extrrdn", ""));
+}
+
+#[test]
+fn transliterates_to_ascii() {
+    assert_eq!(lines("Café"), lines_of("Cafe", ""));
+    assert_eq!(lines("Straße"), lines_of("Strasse", ""));
+    assert_eq!(lines("Mic — 🎤"), lines_of("Mic - ?", ""));
+    assert_eq!(lines("Tab\there"), lines_of("Tab", "here"));
+}
+
+#[test]
+fn never_panics_on_odd_input() {
+    let packet = RTPacket::parse(&{
+        let mut buf = [0u8; 1412];
+        buf[0..4].copy_from_slice(b"VBAN");
+        buf[4] = VBANProtocol::Service as u8;
+        buf[28] = VoiceMeeterType::Potato as u8;
+        for (i, byte) in buf[452..].iter_mut().enumerate() {
+            *byte = (i * 37) as u8;
+        }
+        buf
+    }).unwrap();
+    for label in packet.strip_labels().iter().chain(packet.bus_labels().iter()) {
+        let (top, bottom) = format_label(label);
+        assert!(top.iter().chain(bottom.iter()).all(|c| *c == 0 || (0x20..0x7f).contains(c)));
+    }
+}
@@ -0,0 +1,109 @@
+extern crate vban_xctrl;
+
+use std::thread;
+use std::time::Duration;
+
+use vban_xctrl::*;
+
+mod common;
+
+use common::rt_packet_bytes;
+
+fn rt_event(gain: f32) -> BridgeEvent {
+    BridgeEvent::Vban(Box::new(RTPacket::parse(&rt_packet_bytes(gain)).unwrap()))
+}
+
+fn fader(level: u16) -> BridgeEvent {
+    BridgeEvent::Xctrl(0, XctrlMessage::Fader { id: 0, level })
+}
+
+#[test]
+fn state_keeps_only_the_newest_rt_packet() {
+    let state = WorkQueue::bounded(8, OverflowPolicy::Coalesce(BridgeEvent::supersedes));
+    state.add_work(rt_event(0.0));
+    state.add_work(fader(1));
+    assert_eq!(state.add_work(rt_event(-6.0)), 2);
+    assert_eq!(state.dropped(), 0);
+
+    match state.get_work() {
+        Some(BridgeEvent::Vban(packet)) => assert_eq!(packet.input_gains()[0], -6.0),
+        _ => panic!("expected the newest RT packet first")
+    }
+    assert!(matches!(state.get_work(), Some(BridgeEvent::Xctrl(0, XctrlMessage::Fader { level: 1, .. }))));
+}
+
+#[test]
+fn a_full_state_queue_never_loses_surface_input() {
+    let state = WorkQueue::bounded(4, OverflowPolicy::Coalesce(BridgeEvent::supersedes));
+    for level in 0..4 {
+        state.add_work(fader(level));
+    }
+    let producer = {
+        let state = state.clone();
+        thread::spawn(move || {
+            state.add_work(rt_event(0.0));
+            state.add_work(fader(4));
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(state.len(), 4);
+
+    let mut levels = Vec::new();
+    while levels.len() < 5 {
+        match state.wait_work_timeout(Duration::from_secs(2)) {
+            Some(BridgeEvent::Xctrl(_, XctrlMessage::Fader { level, .. })) => levels.push(level),
+            Some(_) => {},
+            None => panic!("surface input went missing, got {:?}", levels)
+        }
+    }
+    producer.join().unwrap();
+    assert_eq!(levels, vec![0, 1, 2, 3, 4]);
+    assert_eq!(state.dropped(), 0);
+}
+
+#[test]
+fn drop_oldest_makes_room_for_new_work() {
+    let queue = WorkQueue::bounded(2, OverflowPolicy::DropOldest);
+    queue.add_work(1);
+    queue.add_work(2);
+    assert_eq!(queue.add_work(3), 2);
+    assert_eq!(queue.dropped(), 1);
+
+    assert_eq!(queue.get_work(), Some(2));
+    assert_eq!(queue.get_work(), Some(3));
+    assert_eq!(queue.get_work(), None);
+}
+
+#[test]
+fn drop_newest_keeps_what_is_queued() {
+    let queue = WorkQueue::bounded(2, OverflowPolicy::DropNewest);
+    queue.add_work(1);
+    queue.add_work(2);
+    assert_eq!(queue.add_work(3), 2);
+    assert_eq!(queue.add_work(4), 2);
+    assert_eq!(queue.dropped(), 2);
+
+    assert_eq!(queue.get_work(), Some(1));
+    assert_eq!(queue.get_work(), Some(2));
+    assert_eq!(queue.get_work(), None);
+}
+
+#[test]
+fn block_waits_for_a_consumer() {
+    let queue = WorkQueue::bounded(2, OverflowPolicy::Block);
+    queue.add_work(1);
+    queue.add_work(2);
+    let producer = {
+        let queue = queue.clone();
+        thread::spawn(move || queue.add_work(3))
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert!(!producer.is_finished());
+    assert_eq!(queue.len(), 2);
+
+    assert_eq!(queue.get_work(), Some(1));
+    producer.join().unwrap();
+    assert_eq!(queue.dropped(), 0);
+    assert_eq!(queue.get_work(), Some(2));
+    assert_eq!(queue.get_work(), Some(3));
+}
@@ -0,0 +1,83 @@
+#![cfg(feature = "emulator")]
+
+extern crate vban_xctrl;
+
+use std::env;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use vban_xctrl::*;
+
+mod common;
+
+use common::{rt_packet_bytes, SharedBuffer};
+
+const SESSION: &str = "tests/captures/session.capture";
+const SESSION_GOLDEN: &str = "tests/captures/session.golden";
+
+fn incoming(at_ms: u64, link: Link, address: &str, data: Vec<u8>) -> CaptureRecord {
+    CaptureRecord { at: Duration::from_millis(at_ms), link, address: address.to_string(), data }
+}
+
+// A surface connecting, VoiceMeeter sending its state, then a fader move and a mute press
+fn session() -> Vec<CaptureRecord> {
+    let mut emulator = XtouchEmulator::new();
+    vec![
+        incoming(0, Link::XctrlIn, "10.0.0.2:10111", emulator.probe()),
+        incoming(20, Link::VbanIn, "10.0.0.3:6980", rt_packet_bytes(0.0).to_vec()),
+        incoming(130, Link::XctrlIn, "10.0.0.2:10111", emulator.move_fader(1, 12000)),
+        incoming(210, Link::XctrlIn, "10.0.0.2:10111", emulator.press_strip(XctrlButtonType::Mute, 2)),
+        incoming(260, Link::VbanIn, "10.0.0.3:6980", rt_packet_bytes(-6.0).to_vec()),
+    ]
+}
+
+// Run with UPDATE_GOLDEN set to rewrite the capture and its golden output after a deliberate change
+#[test]
+fn replays_the_session_against_its_golden_output() {
+    let config = common::config(&["10.0.0.2"], OutputMode::Midi);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(SESSION, format_capture(&session())).unwrap();
+        let records = load_capture(SESSION).unwrap();
+        fs::write(SESSION_GOLDEN, format_capture(&replay(&config, &records, ReplaySpeed::Fastest).unwrap())).unwrap();
+    }
+
+    let records = load_capture(SESSION).unwrap();
+    assert_eq!(records, session());
+    let expected = load_capture(SESSION_GOLDEN).unwrap();
+    let actual = replay(&config, &records, ReplaySpeed::Fastest).unwrap();
+    if let Some(difference) = first_difference(&expected, &actual) {
+        panic!("the replay no longer matches {}\n{}", SESSION_GOLDEN, difference);
+    }
+}
+
+#[test]
+fn replays_the_same_at_any_speed() {
+    let config = common::config(&["10.0.0.2"], OutputMode::Text);
+    let records = session();
+    let fastest = replay(&config, &records, ReplaySpeed::Fastest).unwrap();
+    assert!(fastest.iter().any(|record| record.link == Link::XctrlOut));
+    assert!(fastest.iter().any(|record| record.link == Link::VbanOut && record.address == "10.0.0.3:6980"));
+    assert_eq!(first_difference(&fastest, &replay(&config, &records, ReplaySpeed::Scaled(20.0)).unwrap()), None);
+}
+
+#[test]
+fn captures_read_back_as_recorded() {
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let capture = Capture::new(Box::new(SharedBuffer(buffer.clone()))).unwrap();
+    capture.record(Link::XctrlIn, "10.0.0.2:10111", &XCTRL_PROBE);
+    capture.record(Link::VbanOut, "10.0.0.3:6980", &[0x56, 0x42, 0x41, 0x4e]);
+
+    let text = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    let records = parse_capture(&text).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].link, Link::XctrlIn);
+    assert_eq!(records[0].data, XCTRL_PROBE.to_vec());
+    assert_eq!(records[1].address, "10.0.0.3:6980");
+    assert!(records[0].at <= records[1].at);
+
+    match parse_capture("# comment\n\n12 vban-in 10.0.0.3:6980 zz\n") {
+        Err(CaptureError::Parse { line: 3, .. }) => {},
+        other => panic!("expected a parse error on line 3, got {:?}", other)
+    }
+}
@@ -0,0 +1,202 @@
+extern crate packed_struct;
+extern crate proptest;
+extern crate vban_xctrl;
+
+use packed_struct::prelude::*;
+use proptest::prelude::*;
+
+use vban_xctrl::*;
+
+fn vban_header() -> impl Strategy<Value = VBANHeader> {
+    any::<u8>().prop_map(|protocol| VBANHeader { vban: *b"VBAN", protocol })
+}
+
+fn service_header() -> impl Strategy<Value = VBANServiceHeader> {
+    (vban_header(), any::<u8>(), any::<u8>(), any::<u8>(), any::<[u8; 16]>(), any::<u32>()).prop_map(
+        |(header, function, service, additional_info, stream_name, frame_id)| {
+            VBANServiceHeader { header, function, service, additional_info, stream_name, frame_id }
+        },
+    )
+}
+
+fn midi_header() -> impl Strategy<Value = VBANMidiHeader> {
+    (vban_header(), any::<u8>(), any::<u8>(), any::<u8>(), any::<[u8; 16]>(), any::<u32>()).prop_map(
+        |(header, bitmode, channels, data_format, stream_name, frame_id)| {
+            VBANMidiHeader { header, bitmode, channels, data_format, stream_name, frame_id }
+        },
+    )
+}
+
+fn text_header() -> impl Strategy<Value = VBANTextHeader> {
+    (vban_header(), any::<u8>(), any::<u8>(), any::<u8>(), any::<[u8; 16]>(), any::<u32>()).prop_map(
+        |(header, bitmode, channels, data_format, stream_name, frame_id)| {
+            VBANTextHeader { header, bitmode, channels, data_format, stream_name, frame_id }
+        },
+    )
+}
+
+fn edition() -> impl Strategy<Value = VoiceMeeterType> {
+    prop_oneof![Just(VoiceMeeterType::Standard), Just(VoiceMeeterType::Banana), Just(VoiceMeeterType::Potato)]
+}
+
+// Every message a surface sends that has a MIDI form, within the ranges the surface uses
+fn control_message() -> impl Strategy<Value = XctrlMessage> {
+    prop_oneof![
+        (0..0x66u8, any::<bool>()).prop_map(|(id, pressed)| XctrlMessage::Button { id, pressed }),
+        (0..9u8, any::<bool>()).prop_map(|(id, touched)| XctrlMessage::FaderTouch { id, touched }),
+        (0..2u8, any::<bool>()).prop_map(|(id, pressed)| XctrlMessage::Footswitch { id, pressed }),
+        (0..9u8, any::<u16>()).prop_map(|(id, level)| XctrlMessage::Fader { id, level }),
+        (0..8u8, -63..=63i8).prop_map(|(id, delta)| XctrlMessage::Encoder { id, delta }),
+        (-63..=63i8).prop_map(|delta| XctrlMessage::JogWheel { delta }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn vban_headers_round_trip(header in vban_header()) {
+        let bytes = header.pack().unwrap();
+        prop_assert_eq!(VBANHeader::parse(&bytes).unwrap(), header);
+    }
+
+    #[test]
+    fn service_headers_round_trip(header in service_header()) {
+        let bytes = header.pack().unwrap();
+        prop_assert_eq!(VBANServiceHeader::parse(&bytes).unwrap(), header);
+    }
+
+    #[test]
+    fn register_packets_round_trip(header in service_header(), packet_ids in prop::collection::vec(any::<u8>(), 128)) {
+        let mut register = RegisterRT { header, packet_ids: [0; 128] };
+        register.packet_ids.copy_from_slice(&packet_ids);
+        prop_assert_eq!(RegisterRT::unpack(&register.pack().unwrap()).unwrap(), register);
+    }
+
+    #[test]
+    fn midi_packets_round_trip(header in midi_header(), body in any::<[u8; 3]>()) {
+        let packet = MidiPacket { header, body };
+        prop_assert_eq!(MidiPacket::unpack(&packet.pack().unwrap()).unwrap(), packet);
+    }
+
+    #[test]
+    fn text_headers_round_trip(header in text_header()) {
+        prop_assert_eq!(VBANTextHeader::unpack(&header.pack().unwrap()).unwrap(), header);
+    }
+
+    #[test]
+    fn text_packets_keep_their_script(text in "[ -~]{0,200}", frame_id in any::<u32>()) {
+        let bytes = TextPacket::new(&text, frame_id).as_bytes();
+        let header = VBANTextHeader::unpack_from_slice(&bytes[..28]).unwrap();
+        prop_assert_eq!(header.frame_id, frame_id);
+        prop_assert_eq!(&bytes[28..], text.as_bytes());
+    }
+
+    // Every field of an RT packet is a plain number or byte array, so any datagram of the right
+    // length that says it's VBAN from a known edition unpacks and packs back to the same bytes
+    #[test]
+    fn rt_packets_round_trip(body in prop::collection::vec(any::<u8>(), 1408), edition in edition()) {
+        let mut bytes = b"VBAN".to_vec();
+        bytes.extend_from_slice(&body);
+        bytes[28] = edition as u8;
+        let packet = RTPacket::parse(&bytes).unwrap();
+        prop_assert_eq!(&packet.pack().unwrap()[..], &bytes[..]);
+    }
+
+    #[test]
+    fn ping_packets_round_trip(header in service_header(), body in prop::collection::vec(any::<u8>(), 676)) {
+        let mut bytes = header.pack().unwrap().to_vec();
+        bytes.extend_from_slice(&body);
+        let ping = PingPacket::parse(&bytes).unwrap();
+        prop_assert_eq!(&ping.pack().unwrap()[..], &bytes[..]);
+    }
+
+    #[test]
+    fn ping_names_read_back(host in "[ -~]{0,64}", application in "[ -~]{0,64}", version in any::<[u8; 4]>()) {
+        let identity = VBANPing0::new(PING_TYPE_VIRTUAL_MIXER, 0, version).with_names("", "", &application, &host);
+        let ping = PingPacket::parse(&ping_reply(&identity, 1)).unwrap();
+        prop_assert_eq!(ping.body.host_name(), host);
+        prop_assert_eq!(ping.body.application_name(), application);
+        prop_assert_eq!(ping.body.version(), version);
+    }
+
+    #[test]
+    fn rt_packet_setters_read_back(edition in edition(), gain in -60.0f32..12.0, bus_gain in -60.0f32..12.0, frame_id in any::<u32>()) {
+        let mut packet = RTPacket::new(edition, "Voicemeeter-RTP", frame_id);
+        packet.set_input_gain(0, gain);
+        packet.set_output_gain(0, bus_gain);
+        packet.set_strip_label(0, "Mic");
+        let packet = RTPacket::parse(&packet.pack().unwrap()).unwrap();
+        prop_assert_eq!(packet.edition(), Ok(edition));
+        prop_assert_eq!(packet.header.frame_id, frame_id);
+        prop_assert!((packet.input_gains()[0] - gain).abs() <= 0.01);
+        prop_assert!((packet.output_gains()[0] - bus_gain).abs() <= 0.01);
+        prop_assert!(packet.strip_labels()[0].starts_with("Mic"));
+    }
+
+    #[test]
+    fn control_messages_round_trip(message in control_message()) {
+        let midi = message.as_midi().unwrap();
+        prop_assert_eq!(XctrlMessage::decode(&midi), message.clone());
+        prop_assert_eq!(XctrlMessage::try_parse(&midi), Ok(vec![message]));
+    }
+
+    #[test]
+    fn batched_control_messages_round_trip(messages in prop::collection::vec(control_message(), 1..16)) {
+        let buf: Vec<u8> = messages.iter().flat_map(|message| message.as_midi().unwrap().to_vec()).collect();
+        prop_assert_eq!(XctrlMessage::try_parse(&buf), Ok(messages));
+    }
+
+    #[test]
+    fn surface_parsers_never_panic(buf in prop::collection::vec(any::<u8>(), 0..64)) {
+        let messages = XctrlMessage::parse(&buf);
+        if let Ok(strict) = XctrlMessage::try_parse(&buf) {
+            prop_assert_eq!(strict, messages);
+        }
+    }
+
+    #[test]
+    fn vban_parsers_never_panic(buf in prop::collection::vec(any::<u8>(), 0..1500)) {
+        let _ = VBANHeader::parse(&buf);
+        let _ = VBANServiceHeader::parse(&buf);
+        if let Ok(ping) = PingPacket::parse(&buf) {
+            let _ = (ping.body.host_name(), ping.body.application_name(), ping.body.user_name());
+        }
+        if let Ok(packet) = RTPacket::parse(&buf) {
+            let _ = (packet.strip_labels(), packet.bus_labels(), packet.input_levels(), packet.input_gain_layers());
+        }
+    }
+
+    // What the bridge sends a surface comes back out of the emulator as the same state
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn surface_updates_round_trip(
+        meters in prop::collection::vec(0..16u8, 8),
+        faders in prop::collection::vec(any::<u16>(), 9),
+        rings in prop::collection::vec((0..4u8, 0..16u8, any::<bool>()), 8),
+        buttons in prop::collection::vec(any::<u8>(), 0x54),
+        displays in prop::collection::vec((any::<u8>(), any::<[u8; 7]>(), any::<[u8; 7]>()), 8)
+    ) {
+        let mut state = XctrlState::blank();
+        for (i, level) in meters.iter().enumerate() {
+            state.meters[i].level = level * 2;
+        }
+        for (i, level) in faders.iter().enumerate() {
+            state.faders[i].level = *level;
+        }
+        for (i, &(mode, value, center)) in rings.iter().enumerate() {
+            state.rings[i] = XctrlEncoderRing { id: i as u8, mode: mode << 4, value, center };
+        }
+        for (i, button) in buttons.iter().enumerate() {
+            state.buttons[i].state = *button;
+        }
+        for (i, &(color, top_text, bottom_text)) in displays.iter().enumerate() {
+            state.displays[i] = XctrlDisplay { id: i as u8, color, top_text, bottom_text };
+        }
+
+        let mut emulator = XtouchEmulator::new();
+        for datagram in state.changes(None) {
+            emulator.apply(&datagram);
+        }
+        prop_assert_eq!(emulator.unknown, 0);
+        prop_assert!(emulator.surface == state);
+    }
+}
@@ -0,0 +1,58 @@
+extern crate packed_struct;
+extern crate vban_xctrl;
+
+use packed_struct::prelude::*;
+use vban_xctrl::*;
+
+// The VMRTSTATE_MODE_* values from the VBAN specification
+#[test]
+fn state_bits_match_voicemeeter() {
+    assert_eq!([STATE_MUTE, STATE_SOLO, STATE_MONO], [0x00000001, 0x00000002, 0x00000004]);
+    assert_eq!([STATE_BUS_A1, STATE_BUS_A2, STATE_BUS_A3, STATE_BUS_A4, STATE_BUS_A5], [0x00001000, 0x00002000, 0x00004000, 0x00008000, 0x00080000]);
+    assert_eq!([STATE_BUS_B1, STATE_BUS_B2, STATE_BUS_B3], [0x00010000, 0x00020000, 0x00040000]);
+}
+
+#[test]
+fn strip_flags_decode() {
+    // Soloed and mono, routed to A1, A3 and B2
+    let strip = StripState::from_bits(0x00025006);
+    assert!(!strip.mute());
+    assert!(strip.solo());
+    assert!(strip.mono());
+    assert!(strip.a1() && !strip.a2() && strip.a3() && !strip.a4() && !strip.a5());
+    assert!(!strip.b1() && strip.b2() && !strip.b3());
+    assert_eq!((0..5).map(|bus| strip.bus_a(bus)).collect::<Vec<_>>(), vec![true, false, true, false, false]);
+    assert_eq!((0..3).map(|bus| strip.bus_b(bus)).collect::<Vec<_>>(), vec![false, true, false]);
+
+    // Muted and routed to A5 and B3 alone, the two flags out of order in the word
+    let strip = StripState::from_bits(0x000c0001);
+    assert!(strip.mute() && !strip.solo() && !strip.mono());
+    assert!(strip.a5() && strip.b3() && !strip.a1() && !strip.b1());
+    assert_eq!(StripState::default().with_mute(true).with_a5(true).with_b3(true), strip);
+}
+
+#[test]
+fn bus_flags_decode() {
+    // Muted, mono and in Composite mode
+    let bus = BusState::from_bits(0x00000045);
+    assert!(bus.mute());
+    assert!(bus.mono());
+    assert_eq!(bus.mode(), BusMode::Composite);
+    assert_eq!(BusState::default().with_mute(true).with_mono(true).with_mode(BusMode::Composite), bus);
+
+    let bus = BusState::from_bits(STATE_SOLO);
+    assert!(!bus.mute() && !bus.mono());
+}
+
+#[test]
+fn rt_packets_carry_the_state_words() {
+    let mut bytes = [0u8; 1412];
+    bytes[0..4].copy_from_slice(b"VBAN");
+    bytes[28] = VoiceMeeterType::Potato as u8;
+    let mut packet = RTPacket::unpack(&bytes).unwrap();
+    packet.strip_state[0] = STATE_MUTE | STATE_BUS_A1;
+    packet.bus_state[1] = STATE_MONO;
+    assert!(packet.strip_states()[0].mute() && packet.strip_states()[0].a1());
+    assert!(!packet.strip_states()[1].mute());
+    assert!(packet.bus_states()[1].mono());
+}
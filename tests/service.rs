@@ -0,0 +1,233 @@
+extern crate packed_struct;
+extern crate vban_xctrl;
+
+use std::fs;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use packed_struct::prelude::*;
+use vban_xctrl::*;
+
+mod common;
+
+use common::{contains, rt_packet_bytes, SharedBuffer};
+
+// Scripted X-Touch and VoiceMeeter ends of a bridge running on in-memory transports
+struct System {
+    xtouch: MemoryTransport,
+    voicemeeter: MemoryTransport
+}
+
+fn transports() -> (MemoryTransport, MemoryTransport, System) {
+    let address = |address: &str| address.parse::<SocketAddr>().unwrap();
+    let (xctrl, xtouch) = MemoryTransport::pair(address("10.0.0.1:10111"), address("10.0.0.2:10111"));
+    let (vban, voicemeeter) = MemoryTransport::pair(address("10.0.0.1:6980"), address("10.0.0.3:6980"));
+    (xctrl, vban, System { xtouch, voicemeeter })
+}
+
+fn start_system(output_mode: OutputMode) -> System {
+    let (xctrl, vban, system) = transports();
+    let bridge = Bridge::new(&common::config(&["10.0.0.2"], output_mode)).unwrap();
+    start(bridge, xctrl, vban, "10.0.0.3:6980".to_string());
+    system
+}
+
+// What VoiceMeeter Banana on the given host answers a ping with
+fn voicemeeter_reply(host_name: &str) -> Vec<u8> {
+    let identity = VBANPing0::new(PING_TYPE_VIRTUAL_MIXER, PING_FEATURE_TXT, [2, 1, 0, 0]).with_names("Voicemeeter Banana", "VB-Audio Software", "Voicemeeter Banana", host_name);
+    ping_reply(&identity, 1)
+}
+
+// How many RT registrations the bridge has sent to an address, going by its capture
+fn registrations(buffer: &Arc<Mutex<Vec<u8>>>, address: &str) -> usize {
+    let text = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+    parse_capture(&text).unwrap().iter().filter(|record| {
+        record.link == Link::VbanOut && record.address == address && record.data.len() == 28 + 128
+    }).count()
+}
+
+fn wait_for_registration(buffer: &Arc<Mutex<Vec<u8>>>, address: &str) {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while registrations(buffer, address) == 0 {
+        assert!(Instant::now() < deadline, "the bridge never registered at {}", address);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+// Waits for a datagram the check accepts, skipping any others
+fn receive(transport: &MemoryTransport, check: &dyn Fn(&[u8]) -> bool) -> Vec<u8> {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+        let mut buf = [0; 2048];
+        if let Ok((amt, _)) = transport.recv_timeout(&mut buf, Duration::from_millis(100)) {
+            if check(&buf[..amt]) {
+                return buf[..amt].to_vec();
+            }
+        }
+    }
+    panic!("nothing matching received");
+}
+
+#[test]
+fn registers_with_voicemeeter() {
+    let system = start_system(OutputMode::Midi);
+    let packet = receive(&system.voicemeeter, &|buf| buf.len() == 28 + 128);
+    assert_eq!(&packet[0..4], b"VBAN");
+    assert_eq!(packet[6], 32);
+}
+
+#[test]
+fn completes_the_surface_handshake() {
+    let system = start_system(OutputMode::Midi);
+    system.xtouch.send_to(&[0xf0, 0x00, 0x20, 0x32, 0x58, 0x54, 0x00, 0xf7], "10.0.0.1:10111").unwrap();
+    receive(&system.xtouch, &|buf| buf == XCTRL_HANDSHAKE_REPLY);
+}
+
+#[test]
+fn rt_packets_reach_the_surface() {
+    let system = start_system(OutputMode::Midi);
+    system.voicemeeter.send_to(&rt_packet_bytes(0.0), "10.0.0.1:6980").unwrap();
+
+    let label = XctrlDisplay::new(0, XctrlDisplayColor::Green, b"Mic\0\0\0\0", &[0; 7]).as_bytes();
+    receive(&system.xtouch, &|buf| contains(buf, &label));
+    let mute = XctrlButton { id: XctrlButtonType::Mute as u8, state: 127 }.as_bytes();
+    receive(&system.xtouch, &|buf| contains(buf, &mute));
+}
+
+#[test]
+fn surface_moves_reach_voicemeeter() {
+    let system = start_system(OutputMode::Midi);
+    system.xtouch.send_to(&[0xe1, 0x00, 0x40], "10.0.0.1:10111").unwrap();
+
+    let packet = receive(&system.voicemeeter, &|buf| buf.len() == 31);
+    assert_eq!(packet, MidiPacket::new([0xe1, 0x00, 0x40], 1).pack().unwrap().to_vec());
+}
+
+#[test]
+fn text_mode_sends_scripts() {
+    let system = start_system(OutputMode::Text);
+    system.voicemeeter.send_to(&rt_packet_bytes(0.0), "10.0.0.1:6980").unwrap();
+    let label = XctrlDisplay::new(0, XctrlDisplayColor::Green, b"Mic\0\0\0\0", &[0; 7]).as_bytes();
+    receive(&system.xtouch, &|buf| contains(buf, &label));
+    system.xtouch.send_to(&[0x90, XctrlButtonType::Mute as u8 + 1, 0x7f], "10.0.0.1:10111").unwrap();
+
+    let packet = receive(&system.voicemeeter, &|buf| buf.len() > 28 && buf[4] == 0x40);
+    assert_eq!(&packet[28..], b"Strip[1].Mute = 1;");
+}
+
+#[test]
+fn bad_packets_are_ignored() {
+    let system = start_system(OutputMode::Midi);
+    system.voicemeeter.send_to(b"VB", "10.0.0.1:6980").unwrap();
+    system.voicemeeter.send_to(&rt_packet_bytes(0.0)[..600], "10.0.0.1:6980").unwrap();
+    system.voicemeeter.send_to(&[0xff; 1412], "10.0.0.1:6980").unwrap();
+    system.xtouch.send_to(&[0xe0, 0x00], "10.0.0.1:10111").unwrap();
+
+    // Both links carry on working afterwards
+    system.voicemeeter.send_to(&rt_packet_bytes(0.0), "10.0.0.1:6980").unwrap();
+    let label = XctrlDisplay::new(0, XctrlDisplayColor::Green, b"Mic\0\0\0\0", &[0; 7]).as_bytes();
+    receive(&system.xtouch, &|buf| contains(buf, &label));
+    system.xtouch.send_to(&[0xe1, 0x00, 0x40], "10.0.0.1:10111").unwrap();
+    receive(&system.voicemeeter, &|buf| buf.len() == 31);
+}
+
+#[test]
+fn unknown_surface_messages_are_left_out() {
+    assert!(decode_xctrl(0, &[0xb0, 0x50, 0x01]).unwrap().is_empty());
+    match decode_xctrl(1, &[0xe1, 0x00, 0x40]).unwrap().as_slice() {
+        [BridgeEvent::Xctrl(1, XctrlMessage::Fader { id: 1, level: 0x4000 })] => {},
+        events => panic!("expected one fader move, got {} events", events.len())
+    }
+}
+
+#[test]
+fn saved_config_changes_apply_live() {
+    let path = std::env::temp_dir().join(format!("vban_xctrl_reload_{}.toml", std::process::id()));
+    let config = |color: &str| format!("[xctrl]\nsurfaces = [\"10.0.0.2\"]\n[vban]\nhost = \"10.0.0.3\"\n[[pages]]\nfaders = [\"strip 1\"]\ncolors = [\"{}\"]\n", color);
+    fs::write(&path, config("red")).unwrap();
+
+    let (xctrl, vban, system) = transports();
+    let bridge = Bridge::new(&Config::load(&path).unwrap()).unwrap();
+    start_watching(bridge, xctrl, vban, "10.0.0.3:6980".to_string(), path.clone());
+    system.voicemeeter.send_to(&rt_packet_bytes(0.0), "10.0.0.1:6980").unwrap();
+    let red = XctrlDisplay::new(0, XctrlDisplayColor::Red, b"Mic\0\0\0\0", &[0; 7]).as_bytes();
+    receive(&system.xtouch, &|buf| contains(buf, &red));
+
+    // A broken save is skipped, the next good one is picked up
+    thread::sleep(Duration::from_millis(10));
+    fs::write(&path, "[[pages]]\nfaders = [\"strip 9\"]\n").unwrap();
+    thread::sleep(Duration::from_millis(1100));
+    fs::write(&path, config("cyan")).unwrap();
+    let cyan = XctrlDisplay::new(0, XctrlDisplayColor::Cyan, b"Mic\0\0\0\0", &[0; 7]).as_bytes();
+    receive(&system.xtouch, &|buf| contains(buf, &cyan));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn answers_pings() {
+    let system = start_system(OutputMode::Midi);
+    let identity = VBANPing0::new(PING_TYPE_VIRTUAL_MIXER, 0, [3, 0, 0, 0]).with_names("Voicemeeter Potato", "VB-Audio", "Voicemeeter Potato", "studio");
+    system.voicemeeter.send_to(&ping_request(&identity, 1), "10.0.0.1:6980").unwrap();
+
+    let reply = receive(&system.voicemeeter, &|buf| PingPacket::is_ping(buf));
+    let reply = PingPacket::parse(&reply).unwrap();
+    assert!(reply.is_reply());
+    assert_eq!(reply.body.application_name(), "vban_xctrl");
+}
+
+#[test]
+fn follows_voicemeeter_to_a_new_address() {
+    let (xctrl, vban, system) = transports();
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let options = StartOptions {
+        capture: Some(Capture::new(Box::new(SharedBuffer(buffer.clone()))).unwrap()),
+        discovery: Some(Discovery { selector: HostSelector::new("voicemeeter-sim"), broadcast: "10.0.0.255:6980".to_string() }),
+        ..StartOptions::default()
+    };
+    // VoiceMeeter was last seen somewhere else
+    start_with(Bridge::new(&common::config(&["10.0.0.2"], OutputMode::Midi)).unwrap(), xctrl, vban, "10.0.0.9:6980".to_string(), options);
+
+    let ping = receive(&system.voicemeeter, &|buf| PingPacket::is_ping(buf));
+    assert!(!PingPacket::parse(&ping).unwrap().is_reply());
+    system.voicemeeter.send_to(&voicemeeter_reply("voicemeeter-sim"), "10.0.0.1:6980").unwrap();
+
+    // The bridge registers for RT packets at the new address and sends there from then on
+    wait_for_registration(&buffer, "10.0.0.3:6980");
+}
+
+#[test]
+fn keeps_to_the_voicemeeter_host_it_found() {
+    let (xctrl, _, _system) = transports();
+    let vban = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let bridge = vban.local_addr().unwrap();
+    let last_seen = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let first = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let moved = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let other = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = |socket: &UdpSocket| socket.local_addr().unwrap().to_string();
+
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let options = StartOptions {
+        capture: Some(Capture::new(Box::new(SharedBuffer(buffer.clone()))).unwrap()),
+        discovery: Some(Discovery { selector: HostSelector::new(""), broadcast: address(&last_seen) }),
+        ..StartOptions::default()
+    };
+    start_with(Bridge::new(&common::config(&["10.0.0.2"], OutputMode::Midi)).unwrap(), xctrl, vban, address(&last_seen), options);
+    wait_for_registration(&buffer, &address(&last_seen));
+
+    // The first VoiceMeeter to answer is registered with once, and kept to by its host name
+    first.send_to(&voicemeeter_reply("STUDIO-PC"), bridge).unwrap();
+    wait_for_registration(&buffer, &address(&first));
+    other.send_to(&voicemeeter_reply("LAPTOP"), bridge).unwrap();
+    first.send_to(&voicemeeter_reply("STUDIO-PC"), bridge).unwrap();
+
+    // The same host answering from somewhere else moves the bridge there
+    moved.send_to(&voicemeeter_reply("STUDIO-PC"), bridge).unwrap();
+    wait_for_registration(&buffer, &address(&moved));
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(registrations(&buffer, &address(&first)), 1);
+    assert_eq!(registrations(&buffer, &address(&moved)), 1);
+    assert_eq!(registrations(&buffer, &address(&other)), 0);
+}
@@ -0,0 +1,161 @@
+#![cfg(feature = "simulator")]
+
+extern crate packed_struct;
+extern crate vban_xctrl;
+
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use packed_struct::prelude::*;
+use vban_xctrl::*;
+
+mod common;
+
+use common::contains;
+
+fn register_rt() -> Vec<u8> {
+    let packet = RegisterRT {
+        header: VBANServiceHeader {
+            header: VBANHeader { vban: *b"VBAN", protocol: VBANProtocol::Service as u8 },
+            function: 0,
+            service: 32,
+            additional_info: 15,
+            stream_name: stream_name("X-Touch meters"),
+            frame_id: 1
+        },
+        packet_ids: [1; 128]
+    };
+    packet.pack().unwrap().to_vec()
+}
+
+#[test]
+fn rt_packets_carry_the_simulated_state() {
+    let mut sim = VoiceMeeterSim::new(VoiceMeeterType::Banana);
+    sim.strips[0] = SimulatedChannel { label: "Mic".to_string(), gain: -6.5, mute: true, level: -10.0, ..SimulatedChannel::default() };
+    sim.strips[4].gain_layers[2] = -20.0;
+    sim.buses[1] = SimulatedChannel { label: "Stream".to_string(), gain: 3.0, mono: true, ..SimulatedChannel::default() };
+
+    let bytes = sim.rt_packet().pack().unwrap();
+    let packet = RTPacket::parse(&bytes).unwrap();
+    assert_eq!(packet.edition(), Ok(VoiceMeeterType::Banana));
+    assert_eq!(packet.input_gains()[0], -6.5);
+    assert_eq!(packet.input_gain_layer(2).unwrap()[4], -20.0);
+    assert_eq!(packet.output_gains()[1], 3.0);
+    assert!(packet.strip_labels()[0].starts_with("Mic\0"));
+    assert!(packet.bus_labels()[1].starts_with("Stream\0"));
+    assert!(packet.strip_states()[0].mute());
+    assert!(packet.bus_states()[1].mono());
+    assert!(packet.input_meters()[0] > 0);
+    assert_eq!(packet.input_meters()[1], 0);
+}
+
+#[test]
+fn text_commands_change_the_state() {
+    let mut sim = VoiceMeeterSim::new(VoiceMeeterType::Potato);
+    let script = "Strip[2].Gain = -6.5; Bus[1].Mute = 1;\nStrip[0].GainLayer[3] = -12.0; Strip[7].Label = \"Music\"; Strip[1].Pan_x = 0.25;";
+    let packet = TextPacket::new(script, 1).as_bytes();
+
+    assert_eq!(sim.handle_packet(&packet), Ok(SimRequest::Applied(5)));
+    assert_eq!(sim.strips[2].gain, -6.5);
+    assert!(sim.buses[1].mute);
+    assert_eq!(sim.strips[0].gain_layers[3], -12.0);
+    assert_eq!(sim.strips[7].label, "Music");
+
+    // Statements it can't follow are skipped, the rest still apply
+    assert_eq!(sim.apply_script("Strip[9].Gain = 0; Bus[0].Solo = 1; Strip[3].Mono = 1"), 1);
+    assert!(sim.strips[3].mono);
+}
+
+#[test]
+fn midi_commands_change_the_state() {
+    let mut sim = VoiceMeeterSim::new(VoiceMeeterType::Potato);
+    let level = FaderRange::default().level(-12.0);
+    let fader = MidiPacket::new([0xe3, (level & 0xff) as u8, (level >> 8) as u8], 1).pack().unwrap();
+    assert_eq!(sim.handle_packet(&fader), Ok(SimRequest::Applied(1)));
+    assert!((sim.strips[3].gain + 12.0).abs() < 0.1);
+
+    // The second bank of faders and buttons is the buses
+    let bus_mute = MidiPacket::new([0x98, XctrlButtonType::Mute as u8 + 2, 0x7f], 2).pack().unwrap();
+    sim.handle_packet(&bus_mute).unwrap();
+    assert!(sim.buses[2].mute);
+    let strip_solo = MidiPacket::new([0x90, XctrlButtonType::Solo as u8 + 1, 0x7f], 3).pack().unwrap();
+    sim.handle_packet(&strip_solo).unwrap();
+    sim.handle_packet(&strip_solo).unwrap();
+    assert!(!sim.strips[1].solo);
+
+    // The master fader has a control of its own rather than a pitch bend channel
+    let master = MidiPacket::new([MASTER_FADER_STATUS, MASTER_FADER_CONTROL, (level >> 8) as u8], 4).pack().unwrap();
+    sim.handle_packet(&master).unwrap();
+    assert!((sim.buses[0].gain + 12.0).abs() < 0.5);
+}
+
+#[test]
+fn registering_subscribes_for_the_timeout() {
+    let mut sim = VoiceMeeterSim::new(VoiceMeeterType::Standard);
+    assert_eq!(sim.handle_packet(&register_rt()), Ok(SimRequest::Register(Duration::from_secs(15))));
+    assert_eq!(sim.handle_packet(b"VB"), Err(VbanError::TooShort { expected: 5, got: 2 }));
+}
+
+#[test]
+fn scenarios_are_checked() {
+    let scenario = SimScenario::parse("edition = \"standard\"\n[[strips]]\nlabel = \"Mic\"\n[[steps]]\nat_ms = 100\nscript = \"Strip[0].Mute = 1\"\n").unwrap();
+    let sim = VoiceMeeterSim::from_scenario(&scenario).unwrap();
+    assert_eq!(sim.strips.len(), 3);
+    assert_eq!(sim.strips[0].label, "Mic");
+    assert_eq!(scenario.steps[0].at_ms, 100);
+
+    assert!(SimScenario::parse("edition = \"banana\"\nbuses = [{}, {}, {}, {}, {}, {}]\n").is_err());
+    assert!(SimScenario::parse("edition = \"deluxe\"\n").is_err());
+}
+
+#[test]
+fn bridge_runs_against_the_simulator() {
+    let address = |address: &str| address.parse::<SocketAddr>().unwrap();
+    let (xctrl, xtouch) = MemoryTransport::pair(address("10.0.0.1:10111"), address("10.0.0.2:10111"));
+    let (vban, voicemeeter) = MemoryTransport::pair(address("10.0.0.1:6980"), address("10.0.0.3:6980"));
+
+    let mut sim = VoiceMeeterSim::new(VoiceMeeterType::Banana);
+    sim.strips[0].label = "Mic".to_string();
+    let steps = vec![SimStep { at_ms: 0, script: "Strip[0].Mute = 1".to_string() }];
+    let (sim, _) = start_simulator(sim, voicemeeter, Duration::from_millis(10), steps);
+    start(Bridge::new(&common::config(&["10.0.0.2"], OutputMode::Text)).unwrap(), xctrl, vban, "10.0.0.3:6980".to_string());
+
+    // The simulator's state shows up on the surface once the bridge has subscribed
+    let label = XctrlDisplay::new(0, XctrlDisplayColor::Green, b"Mic\0\0\0\0", &[0; 7]).as_bytes();
+    let mute = XctrlButton { id: XctrlButtonType::Mute as u8, state: 127 }.as_bytes();
+    let (mut seen_label, mut seen_mute) = (false, false);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !(seen_label && seen_mute) && Instant::now() < deadline {
+        let mut buf = [0; 2048];
+        if let Ok((amt, _)) = xtouch.recv_timeout(&mut buf, Duration::from_millis(100)) {
+            seen_label |= contains(&buf[..amt], &label);
+            seen_mute |= contains(&buf[..amt], &mute);
+        }
+    }
+    assert!(seen_label && seen_mute);
+
+    // And what's done on the surface reaches the simulator
+    xtouch.send_to(&[0x90, XctrlButtonType::Solo as u8 + 1, 0x7f], "10.0.0.1:10111").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while !sim.lock().unwrap().strips[1].solo {
+        assert!(Instant::now() < deadline, "the solo never reached the simulator");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn discovers_the_simulator() {
+    let sim_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sim_address = sim_socket.local_addr().unwrap();
+    let mut sim = VoiceMeeterSim::new(VoiceMeeterType::Potato);
+    sim.host_name = "STUDIO-PC".to_string();
+    start_simulator(sim, sim_socket, Duration::from_millis(20), Vec::new());
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let hosts = discover(&socket, &sim_address.to_string(), Duration::from_millis(500)).unwrap();
+    assert_eq!(hosts.len(), 1);
+    assert_eq!(hosts[0].address, sim_address);
+    assert_eq!(hosts[0].host_name, "STUDIO-PC");
+    assert_eq!(hosts[0].application, "Voicemeeter Potato");
+    assert!(HostSelector::new("").matches(&hosts[0]));
+}
@@ -0,0 +1,148 @@
+extern crate packed_struct;
+extern crate vban_xctrl;
+
+use packed_struct::prelude::*;
+use vban_xctrl::*;
+
+mod common;
+
+#[test]
+fn parses_rt_packets() {
+    let packet = RTPacket::parse(&common::rt_packet_bytes(-6.0)).unwrap();
+    assert_eq!(packet.edition(), Ok(VoiceMeeterType::Banana));
+    assert_eq!(packet.input_gains()[0], -6.0);
+}
+
+#[test]
+fn refuses_malformed_packets() {
+    let good = common::rt_packet_bytes(0.0);
+    assert_eq!(VBANHeader::parse(&good[..3]), Err(VbanError::TooShort { expected: 5, got: 3 }));
+    assert_eq!(VBANServiceHeader::parse(&good[..20]), Err(VbanError::TooShort { expected: 28, got: 20 }));
+    assert_eq!(RTPacket::parse(&good[..1000]).unwrap_err(), VbanError::WrongLength { expected: 1412, got: 1000 });
+
+    let mut not_vban = good;
+    not_vban[0..4].copy_from_slice(b"VBAM");
+    assert_eq!(RTPacket::parse(&not_vban).unwrap_err(), VbanError::NotVban);
+    assert_eq!(RTPacket::parse(&[]).unwrap_err(), VbanError::TooShort { expected: 5, got: 0 });
+
+    let mut unknown_edition = good;
+    unknown_edition[28] = 4;
+    assert_eq!(RTPacket::parse(&unknown_edition).unwrap_err(), VbanError::UnknownEdition(4));
+    unknown_edition[28] = 0;
+    assert_eq!(RTPacket::parse(&unknown_edition).unwrap_err(), VbanError::UnknownEdition(0));
+}
+
+#[test]
+fn labels_that_are_not_utf8_do_not_panic() {
+    let mut buf = common::rt_packet_bytes(0.0);
+    buf[452..456].copy_from_slice(&[b'M', 0xff, 0xfe, b'c']);
+    let labels = RTPacket::parse(&buf).unwrap().strip_labels();
+    assert!(labels[0].starts_with("M\u{fffd}\u{fffd}c"));
+}
+
+#[test]
+fn text_packets_have_the_vban_text_layout() {
+    let bytes = TextPacket::new("Strip[0].Mute = 1;", 0x01020304).as_bytes();
+    let mut expected = vec![
+        b'V', b'B', b'A', b'N',
+        0x40, // Text protocol, sample rate index 0
+        0x00, 0x00, // No bit mode or channels
+        0x10, // UTF-8
+        b'C', b'o', b'm', b'm', b'a', b'n', b'd', b'1', 0, 0, 0, 0, 0, 0, 0, 0,
+        0x04, 0x03, 0x02, 0x01 // Frame counter, little endian
+    ];
+    expected.extend_from_slice(b"Strip[0].Mute = 1;");
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn commands_render_as_voicemeeter_scripts() {
+    let script = |channel, parameter| VoiceMeeterCommand::new(channel, parameter).as_script();
+    assert_eq!(script(VoiceMeeterChannel::Strip(3), VoiceMeeterParameter::Gain(-6.5)), "Strip[3].Gain = -6.5;");
+    assert_eq!(script(VoiceMeeterChannel::Strip(0), VoiceMeeterParameter::Gain(12.0)), "Strip[0].Gain = 12.0;");
+    assert_eq!(script(VoiceMeeterChannel::Bus(0), VoiceMeeterParameter::Mute(true)), "Bus[0].Mute = 1;");
+    assert_eq!(script(VoiceMeeterChannel::Bus(4), VoiceMeeterParameter::Mono(false)), "Bus[4].Mono = 0;");
+    assert_eq!(script(VoiceMeeterChannel::Strip(1), VoiceMeeterParameter::Solo(true)), "Strip[1].Solo = 1;");
+}
+
+#[test]
+fn gain_layers_read_back_per_bus() {
+    let mut bytes = [0u8; 1412];
+    bytes[0..4].copy_from_slice(b"VBAN");
+    bytes[28] = VoiceMeeterType::Potato as u8;
+    // Layer 2 follows the 8 strips of layer 1, which starts at byte 308
+    bytes[324..326].copy_from_slice(&(-1200i16).to_le_bytes());
+    let packet = RTPacket::unpack(&bytes).unwrap();
+    assert_eq!(packet.input_gain_layer(1).unwrap()[0], -12.0);
+    assert_eq!(packet.input_gain_layer(0).unwrap()[0], 0.0);
+    assert!(packet.input_gain_layer(8).is_none());
+
+    let command = VoiceMeeterCommand::new(VoiceMeeterChannel::Strip(0), VoiceMeeterParameter::GainLayer(1, -12.0));
+    assert_eq!(command.as_script(), "Strip[0].GainLayer[1] = -12.0;");
+}
+
+// Every input and output channel reads back as its own number, and every label slot is "S0" to
+// "S7" or "B0" to "B7", so each edition's picks show which slots it reads
+fn numbered_packet(edition: VoiceMeeterType) -> RTPacket {
+    let mut bytes = [0u8; 1412];
+    bytes[0..4].copy_from_slice(b"VBAN");
+    bytes[28] = edition as u8;
+    for channel in 0..34 {
+        bytes[44 + (channel * 2)..46 + (channel * 2)].copy_from_slice(&(u16::MAX - channel as u16).to_le_bytes());
+    }
+    for channel in 0..64 {
+        bytes[112 + (channel * 2)..114 + (channel * 2)].copy_from_slice(&(u16::MAX - channel as u16).to_le_bytes());
+    }
+    for i in 0..8 {
+        bytes[452 + (i * 60)..454 + (i * 60)].copy_from_slice(format!("S{}", i).as_bytes());
+        bytes[932 + (i * 60)..934 + (i * 60)].copy_from_slice(format!("B{}", i).as_bytes());
+    }
+    RTPacket::parse(&bytes).unwrap()
+}
+
+fn trimmed(labels: Vec<String>) -> Vec<String> {
+    labels.iter().map(|label| label.trim_end_matches('\0').to_string()).collect()
+}
+
+#[test]
+fn standard_reads_its_own_slots() {
+    let packet = numbered_packet(VoiceMeeterType::Standard);
+    // Two stereo physical strips, then the virtual strip's first two of 8 channels
+    assert_eq!(packet.input_levels(), vec![[0, 1], [2, 3], [4, 5]]);
+    assert_eq!(packet.output_levels(), vec![[0, 1], [8, 9]]);
+    assert_eq!(packet.input_meters().len(), 3);
+    assert_eq!(packet.output_meters().len(), 2);
+    assert_eq!(trimmed(packet.strip_labels()), vec!["S0", "S1", "S2"]);
+    assert_eq!(trimmed(packet.bus_labels()), vec!["B0", "B1"]);
+}
+
+#[test]
+fn banana_reads_its_own_slots() {
+    let packet = numbered_packet(VoiceMeeterType::Banana);
+    assert_eq!(packet.input_levels(), vec![[0, 1], [2, 3], [4, 5], [6, 7], [14, 15]]);
+    assert_eq!(packet.output_levels(), vec![[0, 1], [8, 9], [16, 17], [24, 25], [32, 33]]);
+    assert_eq!(packet.input_meters().len(), 5);
+    assert_eq!(packet.output_meters().len(), 5);
+    assert_eq!(trimmed(packet.strip_labels()), vec!["S0", "S1", "S2", "S3", "S4"]);
+    assert_eq!(trimmed(packet.bus_labels()), vec!["B0", "B1", "B2", "B3", "B4"]);
+}
+
+#[test]
+fn potato_reads_every_slot() {
+    let packet = numbered_packet(VoiceMeeterType::Potato);
+    assert_eq!(packet.input_levels(), vec![[0, 1], [2, 3], [4, 5], [6, 7], [8, 9], [10, 11], [18, 19], [26, 27]]);
+    assert_eq!(packet.output_levels(), (0..8).map(|bus| [bus * 8, (bus * 8) + 1]).collect::<Vec<_>>());
+    assert_eq!(trimmed(packet.strip_labels()), (0..8).map(|i| format!("S{}", i)).collect::<Vec<_>>());
+    assert_eq!(trimmed(packet.bus_labels()), (0..8).map(|i| format!("B{}", i)).collect::<Vec<_>>());
+}
+
+#[test]
+fn unknown_editions_have_no_channels() {
+    let mut packet = numbered_packet(VoiceMeeterType::Potato);
+    packet.voicemeeter_type = 9;
+    assert_eq!(packet.edition(), Err(VbanError::UnknownEdition(9)));
+    assert!(packet.input_levels().is_empty());
+    assert!(packet.output_levels().is_empty());
+    assert!(packet.strip_labels().is_empty());
+    assert!(packet.input_gains().is_empty());
+}
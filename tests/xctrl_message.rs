@@ -0,0 +1,62 @@
+extern crate vban_xctrl;
+
+use vban_xctrl::*;
+
+#[test]
+fn decodes_handshake_probe() {
+    let messages = XctrlMessage::parse(&[0xf0, 0x00, 0x20, 0x32, 0x58, 0x54, 0x00, 0xf7]);
+    assert_eq!(messages, vec![XctrlMessage::Probe]);
+}
+
+#[test]
+fn decodes_device_id_serial() {
+    let buf = [0xf0, 0x00, 0x00, 0x66, 0x58, 0x01, 0x30, 0x31, 0x35, 0x36, 0x34, 0x30, 0x38, 0x33, 0x39, 0x33, 0x44, 0xf7];
+    let messages = XctrlMessage::parse(&buf);
+    assert_eq!(messages, vec![XctrlMessage::DeviceId { serial: "0156408393D".to_string() }]);
+}
+
+#[test]
+fn decodes_controls() {
+    assert_eq!(XctrlMessage::decode(&[0x90, 0x10, 0x7f]), XctrlMessage::Button { id: 0x10, pressed: true });
+    assert_eq!(XctrlMessage::decode(&[0x90, 0x69, 0x00]), XctrlMessage::FaderTouch { id: 1, touched: false });
+    assert_eq!(XctrlMessage::decode(&[0x90, 0x67, 0x7f]), XctrlMessage::Footswitch { id: 1, pressed: true });
+    assert_eq!(XctrlMessage::decode(&[0xe3, 0x12, 0x34]), XctrlMessage::Fader { id: 3, level: 0x3412 });
+    assert_eq!(XctrlMessage::decode(&[0xb0, 0x12, 0x03]), XctrlMessage::Encoder { id: 2, delta: 3 });
+    assert_eq!(XctrlMessage::decode(&[0xb0, 0x3c, 0x41]), XctrlMessage::JogWheel { delta: -1 });
+}
+
+#[test]
+fn splits_batched_datagrams() {
+    let buf = [0x90, 0x10, 0x7f, 0xe0, 0x00, 0x40, 0xf0, 0x00, 0x20, 0x32, 0x58, 0x54, 0x00, 0xf7, 0xb0, 0x17, 0x42];
+    let messages = XctrlMessage::parse(&buf);
+    assert_eq!(messages, vec![
+        XctrlMessage::Button { id: 0x10, pressed: true },
+        XctrlMessage::Fader { id: 0, level: 0x4000 },
+        XctrlMessage::Probe,
+        XctrlMessage::Encoder { id: 7, delta: -2 },
+    ]);
+}
+
+#[test]
+fn truncated_messages_do_not_panic() {
+    let messages = XctrlMessage::parse(&[0x90, 0x10]);
+    assert_eq!(messages, vec![XctrlMessage::Unknown(vec![0x90, 0x10])]);
+}
+
+#[test]
+fn midi_form_matches_the_original_bytes() {
+    for buf in [[0x90, 0x10, 0x7f], [0xe7, 0x7f, 0x7f], [0xb0, 0x15, 0x45], [0x90, 0x70, 0x7f]].iter() {
+        assert_eq!(XctrlMessage::decode(buf).as_midi(), Some(*buf));
+    }
+}
+
+#[test]
+fn try_parse_refuses_malformed_datagrams() {
+    assert_eq!(XctrlMessage::try_parse(&[]), Err(XctrlError::Empty));
+    assert_eq!(XctrlMessage::try_parse(&[0x90, 0x10, 0x7f, 0xe0, 0x00]), Err(XctrlError::Truncated(vec![0xe0, 0x00])));
+    assert_eq!(XctrlMessage::try_parse(&[0xf0, 0x00, 0x20]), Err(XctrlError::Unterminated(vec![0xf0, 0x00, 0x20])));
+    assert_eq!(XctrlMessage::try_parse(&[0x10, 0x7f]), Err(XctrlError::StrayData(0x10)));
+
+    // Well formed messages the bridge doesn't use are still fine
+    assert_eq!(XctrlMessage::try_parse(&[0xb0, 0x40, 0x01]), Ok(vec![XctrlMessage::Unknown(vec![0xb0, 0x40, 0x01])]));
+}